@@ -1,11 +1,47 @@
-use common::{get_random, init_random_generator, TimeKeeper};
+use common::animate::{self, Animation, Cell, Frame};
+use common::board::{Board, BoardError, GameRecord};
+use common::cli::{csv_field, Args, CliError, OutputFormat, DIRECTION_NAMES};
+use common::grid::{Grid, PointGrid};
+use common::spec::{AiSpec, Algorithm, ParamDef, Params, Registry, SpecError};
+use common::tune::{self, TuneOptions};
+use common::{get_random, init_random_generator, measure_throughput, GameConfig, TimeKeeper};
 
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{self, AtomicBool, AtomicUsize};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
 
-const H: i32 = 30;
-const W: i32 = 30;
-const END_TURN: i32 = 100;
+const DEFAULT_CONFIG: GameConfig = GameConfig::new(30, 30, 100, 1);
+/// Board small enough for the exact solver, used by `gap`.
+const SMALL_CONFIG: GameConfig = GameConfig::new(5, 5, 12, 1);
+
+/// Optional rules on top of the plain maze.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct MazeRules {
+    /// Generate impassable cells, keeping every open cell reachable.
+    walls: bool,
+    /// Turns after which a collected cell gets its points back.
+    regrow: Option<usize>,
+    /// Turns between spawns of new points, drawn from the board.
+    spawn_every: Option<usize>,
+}
+
+/// Points placed on a cell at the start of a turn, replacing what is there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Spawn {
+    turn: i32,
+    x: usize,
+    y: usize,
+    value: usize,
+}
+
+/// An AI returns the actions it plans to take, starting with the next one.
+struct Ai(String, Action);
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 struct Coord {
@@ -19,14 +55,87 @@ impl Coord {
     }
 }
 
+/// SplitMix64, used to derive the Zobrist keys of the cells.
+const fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Collected cells waiting to get their points back.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Regrowth {
+    delay: Option<usize>,
+    // Cells by the turn they regrow on, earliest first.
+    pending: VecDeque<(i32, Coord)>,
+}
+
+impl Regrowth {
+    /// Hash key of `coord` collected on `turn`, which tells apart the turns
+    /// a cell regrows on.
+    fn key(&self, config: &GameConfig, turn: i32, coord: &Coord) -> u64 {
+        match self.delay {
+            None => MazeState::collected_key(config, coord),
+            Some(delay) => MazeState::regrow_key(config, coord, turn + delay as i32),
+        }
+    }
+
+    /// Records `coord` as collected on `turn` and returns its hash key.
+    fn collect(&mut self, config: &GameConfig, turn: i32, coord: &Coord) -> u64 {
+        if let Some(delay) = self.delay {
+            self.pending.push_back((turn + delay as i32, coord.clone()));
+        }
+        self.key(config, turn, coord)
+    }
+
+    /// Forgets that `coord` was collected and returns its hash key.
+    fn release(&mut self, config: &GameConfig, coord: &Coord) -> u64 {
+        match self
+            .pending
+            .iter()
+            .position(|(_, pending)| pending == coord)
+        {
+            Some(i) => {
+                let (due, _) = self.pending.remove(i).unwrap();
+                MazeState::regrow_key(config, coord, due)
+            }
+            None => MazeState::collected_key(config, coord),
+        }
+    }
+
+    /// Pops a cell regrowing on `turn` along with its hash key.
+    fn pop_due(&mut self, config: &GameConfig, turn: i32) -> Option<(Coord, u64)> {
+        if self.pending.front()?.0 != turn {
+            return None;
+        }
+        let (due, coord) = self.pending.pop_front().unwrap();
+        let key = MazeState::regrow_key(config, &coord, due);
+
+        Some((coord, key))
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 struct MazeState {
-    points: Vec<Vec<i32>>,
+    config: GameConfig,
+    points: PointGrid,
+    walls: Arc<Grid<bool>>,
     turn: i32,
-    character: Coord,
+    // Characters in the order they move within a turn.
+    characters: Vec<Coord>,
+    // Index of the character moving next; the turn ends after the last one.
+    mover: usize,
     game_score: i32,
     evaluated_score: i32,
-    first_action: usize,
+    // Actions taken since the root of the current search.
+    actions: Vec<usize>,
+    regrowth: Regrowth,
+    // Every spawn of the game and the index of the next one.
+    spawns: Arc<Vec<Spawn>>,
+    next_spawn: usize,
+    // Zobrist hash of the character positions, the mover and the collected cells.
+    hash: u64,
 }
 
 impl Ord for MazeState {
@@ -45,87 +154,645 @@ impl PartialOrd for MazeState {
 
 impl MazeState {
     #[allow(non_upper_case_globals)]
-    const dx: [i32; 4] = [1, -1, 0, 0];
+    const dx: [i32; 5] = [1, -1, 0, 0, 0];
     #[allow(non_upper_case_globals)]
-    const dy: [i32; 4] = [0, 0, 1, -1];
-    pub fn new() -> MazeState {
-        let x = get_random(W as usize) as i32;
-        let y = get_random(H as usize) as i32;
-        let character = Coord::new(x, y);
-        let mut points = vec![vec![0; H as usize]; W as usize];
-
-        for i in 0..W {
-            for j in 0..H {
-                if i == x && j == y {
+    const dy: [i32; 5] = [0, 0, 1, -1, 0];
+    /// Action of a character boxed in by walls and other characters.
+    const STAY: usize = 4;
+
+    pub fn new(config: GameConfig, rules: MazeRules) -> MazeState {
+        let mut characters: Vec<Coord> = Vec::with_capacity(config.character_n);
+        while characters.len() < config.character_n {
+            let x = get_random(config.h) as i32;
+            let y = get_random(config.w) as i32;
+            let character = Coord::new(x, y);
+            if !characters.contains(&character) {
+                characters.push(character);
+            }
+        }
+        let walls = if rules.walls {
+            Self::generate_walls(&config, &characters)
+        } else {
+            Grid::new(config.h, config.w, false)
+        };
+        let mut points = Grid::new(config.h, config.w, 0);
+
+        for i in 0..config.h as i32 {
+            for j in 0..config.w as i32 {
+                if characters.contains(&Coord::new(i, j)) || walls[(i as usize, j as usize)] {
                     continue;
                 }
-                points[i as usize][j as usize] = get_random(10) as i32;
+                points[(i as usize, j as usize)] = get_random(10);
             }
         }
 
+        Self::from_parts(config, rules, points, walls, characters)
+    }
+
+    /// Walls come from the board; regrowth and spawns from `rules`.
+    pub fn from_board(board: &Board, rules: MazeRules) -> Result<MazeState, BoardError> {
+        if board.characters.is_empty() {
+            board.expect_characters(1)?;
+        }
+        board.expect_distinct_characters()?;
+        let mut points = Grid::from_rows(&board.points);
+
+        let config = board.config();
+        let characters: Vec<Coord> = board
+            .characters
+            .iter()
+            .map(|&(x, y)| Coord::new(x, y))
+            .collect();
+        let mut walls = Grid::new(config.h, config.w, false);
+        for &(x, y) in &board.walls {
+            walls[(x as usize, y as usize)] = true;
+        }
+        // As in `new`, nothing is left to collect where a character starts.
+        for character in &characters {
+            points[(character.x as usize, character.y as usize)] = 0;
+        }
+
+        Ok(Self::from_parts(config, rules, points, walls, characters))
+    }
+
+    fn from_parts(
+        config: GameConfig,
+        rules: MazeRules,
+        points: Grid<usize>,
+        walls: Grid<bool>,
+        characters: Vec<Coord>,
+    ) -> MazeState {
+        let spawns = match rules.spawn_every {
+            Some(interval) => Self::spawn_schedule(&config, &points, &walls, interval),
+            None => Vec::new(),
+        };
+
         MazeState {
-            points,
+            config,
+            points: PointGrid::new(points),
+            walls: Arc::new(walls),
             turn: 0,
-            character,
+            hash: Self::characters_hash(&config, &characters),
+            characters,
+            mover: 0,
             game_score: 0,
             evaluated_score: 0,
-            first_action: 0,
+            actions: Vec::new(),
+            regrowth: Regrowth {
+                delay: rules.regrow,
+                pending: VecDeque::new(),
+            },
+            spawns: Arc::new(spawns),
+            next_spawn: 0,
         }
     }
 
+    /// A spawn of 1 to 9 points on an open cell every `interval` turns. The
+    /// generator is seeded by the points of the board rather than the game
+    /// seed, so a recorded board replays with the same spawns.
+    fn spawn_schedule(
+        config: &GameConfig,
+        points: &Grid<usize>,
+        walls: &Grid<bool>,
+        interval: usize,
+    ) -> Vec<Spawn> {
+        let open: Vec<(usize, usize)> = (0..config.h)
+            .flat_map(|x| (0..config.w).map(move |y| (x, y)))
+            .filter(|&cell| !walls[cell])
+            .collect();
+        let mut seed = points
+            .rows()
+            .flatten()
+            .fold(0, |seed, &point| splitmix64(seed ^ point as u64));
+        let mut next = || {
+            seed = splitmix64(seed);
+            seed as usize
+        };
+
+        (1..)
+            .map(|k| k * interval)
+            .take_while(|&turn| turn < config.end_turn)
+            .map(|turn| {
+                let (x, y) = open[next() % open.len()];
+                Spawn {
+                    turn: turn as i32,
+                    x,
+                    y,
+                    value: 1 + next() % 9,
+                }
+            })
+            .collect()
+    }
+
+    /// Stick-knocking: a pillar on every odd cell, each knocked over onto a
+    /// random neighbour, where only the first row may knock upwards. Walls
+    /// that still cut cells off are then knocked down.
+    fn generate_walls(config: &GameConfig, characters: &[Coord]) -> Grid<bool> {
+        let mut walls = Grid::new(config.h, config.w, false);
+        for x in (1..config.h as i32).step_by(2) {
+            for y in (1..config.w as i32).step_by(2) {
+                if characters.contains(&Coord::new(x, y)) {
+                    continue;
+                }
+                walls[(x as usize, y as usize)] = true;
+                let directions: &[usize] = if x == 1 { &[0, 1, 2, 3] } else { &[0, 2, 3] };
+                let direction = directions[get_random(directions.len())];
+                let (nx, ny) = (x + Self::dx[direction], y + Self::dy[direction]);
+                if !config.is_inside(nx, ny) || characters.contains(&Coord::new(nx, ny)) {
+                    continue;
+                }
+                walls[(nx as usize, ny as usize)] = true;
+            }
+        }
+        Self::connect(&mut walls, config, &characters[0]);
+
+        walls
+    }
+
+    /// Cells reachable from `from` without crossing a wall.
+    fn reachable(walls: &Grid<bool>, config: &GameConfig, from: &Coord) -> Grid<bool> {
+        let mut reached = Grid::new(config.h, config.w, false);
+        reached[(from.x as usize, from.y as usize)] = true;
+        let mut queue = VecDeque::from([(from.x, from.y)]);
+        while let Some((x, y)) = queue.pop_front() {
+            for i in 0..4 {
+                let (nx, ny) = (x + Self::dx[i], y + Self::dy[i]);
+                if !config.is_inside(nx, ny) {
+                    continue;
+                }
+                let cell = (nx as usize, ny as usize);
+                if walls[cell] || reached[cell] {
+                    continue;
+                }
+                reached[cell] = true;
+                queue.push_back((nx, ny));
+            }
+        }
+
+        reached
+    }
+
+    /// Knocks down the fewest walls between the reachable area and the
+    /// nearest cut-off cell until every open cell is reachable.
+    fn connect(walls: &mut Grid<bool>, config: &GameConfig, character: &Coord) {
+        loop {
+            let reached = Self::reachable(walls, config, character);
+            let mut parent = Grid::new(config.h, config.w, None);
+            let mut queue = VecDeque::new();
+            for x in 0..config.h {
+                for y in 0..config.w {
+                    if reached[(x, y)] {
+                        queue.push_back((x, y));
+                    }
+                }
+            }
+            let mut cut_off = None;
+            while let Some((x, y)) = queue.pop_front() {
+                if !walls[(x, y)] && !reached[(x, y)] {
+                    cut_off = Some((x, y));
+                    break;
+                }
+                for i in 0..4 {
+                    let (nx, ny) = (x as i32 + Self::dx[i], y as i32 + Self::dy[i]);
+                    if !config.is_inside(nx, ny) {
+                        continue;
+                    }
+                    let cell = (nx as usize, ny as usize);
+                    if reached[cell] || parent[cell].is_some() {
+                        continue;
+                    }
+                    parent[cell] = Some((x, y));
+                    queue.push_back(cell);
+                }
+            }
+
+            let Some(mut cell) = cut_off else {
+                return;
+            };
+            while let Some(previous) = parent[cell] {
+                walls[cell] = false;
+                cell = previous;
+            }
+        }
+    }
+
+    /// Calls `visit(x, y, distance)` on every cell within `radius` steps of
+    /// the nearest of `from` in BFS order, walking around walls.
+    fn visit_within(
+        &self,
+        from: &[Coord],
+        radius: usize,
+        mut visit: impl FnMut(usize, usize, usize),
+    ) {
+        let mut distances = Grid::new(self.config.h, self.config.w, usize::MAX);
+        let mut queue = VecDeque::new();
+        for coord in from {
+            let cell = (coord.x as usize, coord.y as usize);
+            if distances[cell] == 0 {
+                continue;
+            }
+            distances[cell] = 0;
+            queue.push_back((coord.x, coord.y));
+        }
+        while let Some((x, y)) = queue.pop_front() {
+            let distance = distances[(x as usize, y as usize)];
+            visit(x as usize, y as usize, distance);
+            if distance == radius {
+                continue;
+            }
+            for i in 0..4 {
+                let (nx, ny) = (x + Self::dx[i], y + Self::dy[i]);
+                if !self.config.is_inside(nx, ny) || self.is_wall(nx, ny) {
+                    continue;
+                }
+                let cell = (nx as usize, ny as usize);
+                if distances[cell] != usize::MAX {
+                    continue;
+                }
+                distances[cell] = distance + 1;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+
+    fn is_wall(&self, x: i32, y: i32) -> bool {
+        self.walls[(x as usize, y as usize)]
+    }
+
+    /// Key of character `index` standing on `coord`. Even, like the keys of
+    /// the first character, so it never meets a [`Self::collected_key`].
+    fn character_key(config: &GameConfig, index: usize, coord: &Coord) -> u64 {
+        let cell = coord.x as usize * config.w + coord.y as usize;
+        splitmix64((index * config.h * config.w + cell) as u64 * 2)
+    }
+
+    /// Key of the character moving next, zero for the first so that a single
+    /// character hashes as before.
+    const fn mover_key(mover: usize) -> u64 {
+        if mover == 0 {
+            0
+        } else {
+            splitmix64(u64::MAX - mover as u64)
+        }
+    }
+
+    fn characters_hash(config: &GameConfig, characters: &[Coord]) -> u64 {
+        characters.iter().enumerate().fold(0, |hash, (i, coord)| {
+            hash ^ Self::character_key(config, i, coord)
+        })
+    }
+
+    fn collected_key(config: &GameConfig, coord: &Coord) -> u64 {
+        splitmix64((coord.x as usize * config.w + coord.y as usize) as u64 * 2 + 1)
+    }
+
+    /// Key of a collected cell that regrows on turn `due`.
+    fn regrow_key(config: &GameConfig, coord: &Coord, due: i32) -> u64 {
+        splitmix64(Self::collected_key(config, coord) ^ due as u64)
+    }
+
+    fn to_board(&self) -> Board {
+        Board {
+            h: self.config.h,
+            w: self.config.w,
+            end_turn: self.remaining_turns(),
+            characters: self
+                .characters
+                .iter()
+                .map(|character| (character.x, character.y))
+                .collect(),
+            points: self.points.to_rows(),
+            walls: (0..self.config.h as i32)
+                .flat_map(|x| (0..self.config.w as i32).map(move |y| (x, y)))
+                .filter(|&(x, y)| self.is_wall(x, y))
+                .collect(),
+        }
+    }
+
+    const fn remaining_turns(&self) -> usize {
+        self.config.end_turn - self.turn as usize
+    }
+
+    /// Single-character moves left, one per character and turn.
+    fn remaining_steps(&self) -> usize {
+        self.remaining_turns() * self.characters.len() - self.mover
+    }
+
+    /// The character moved by the last action.
+    fn last_moved(&self) -> &Coord {
+        let n = self.characters.len();
+        &self.characters[(self.mover + n - 1) % n]
+    }
+
     const fn is_done(&self) -> bool {
-        self.turn == END_TURN
+        self.turn == self.config.end_turn as i32
     }
 
+    /// Actions of the character moving next.
     fn legal_action(&self) -> Vec<usize> {
+        self.legal_moves(&self.characters, self.mover)
+    }
+
+    /// Moves of `characters[mover]` that stay on the board and off walls and
+    /// the other characters, or [`Self::STAY`] if there are none.
+    fn legal_moves(&self, characters: &[Coord], mover: usize) -> Vec<usize> {
+        let character = &characters[mover];
         let mut actions = Vec::new();
         for i in 0..4 {
-            let nx = self.character.x + Self::dx[i];
-            let ny = self.character.y + Self::dy[i];
-            if !(0..W).contains(&nx) || !(0..H).contains(&ny) {
+            let nx = character.x + Self::dx[i];
+            let ny = character.y + Self::dy[i];
+            if !self.config.is_inside(nx, ny)
+                || self.is_wall(nx, ny)
+                || characters.contains(&Coord::new(nx, ny))
+            {
                 continue;
             }
             actions.push(i);
         }
+        if actions.is_empty() {
+            actions.push(Self::STAY);
+        }
 
         actions
     }
 
+    /// Every collision-free combination of one action per character for the
+    /// rest of the current turn, in the order the characters move.
+    fn legal_joint_actions(&self) -> Vec<Vec<usize>> {
+        let mut joint_actions = Vec::new();
+        let mut state = self.clone();
+        state.actions.clear();
+        state.collect_joint_actions(&mut joint_actions);
+
+        joint_actions
+    }
+
+    fn collect_joint_actions(&self, joint_actions: &mut Vec<Vec<usize>>) {
+        for action in self.legal_action() {
+            let mut next_state = self.clone();
+            next_state.advance(action);
+            next_state.actions.push(action);
+            if next_state.mover == 0 {
+                joint_actions.push(next_state.actions);
+            } else {
+                next_state.collect_joint_actions(joint_actions);
+            }
+        }
+    }
+
+    /// Moves the character whose turn it is; the turn ends once every
+    /// character has moved.
     fn advance(&mut self, action: usize) {
-        self.character.x += Self::dx[action];
-        self.character.y += Self::dy[action];
-        let point = &mut self.points[self.character.x as usize][self.character.y as usize];
-        if *point > 0 {
-            self.game_score += *point;
-            *point = 0;
+        let mover = self.mover;
+        let character = &mut self.characters[mover];
+        self.hash ^= Self::character_key(&self.config, mover, character);
+        character.x += Self::dx[action];
+        character.y += Self::dy[action];
+        self.hash ^= Self::character_key(&self.config, mover, character);
+        let point = self
+            .points
+            .consume(character.x as usize, character.y as usize);
+        if point > 0 {
+            self.game_score += point as i32;
+            self.hash ^= self.regrowth.collect(&self.config, self.turn, character);
+        }
+        self.hash ^= Self::mover_key(mover);
+        self.mover = (mover + 1) % self.characters.len();
+        self.hash ^= Self::mover_key(self.mover);
+        if self.mover == 0 {
+            self.pass_turn();
         }
+    }
+
+    /// Starts the next turn: collected cells due regrow and scheduled points
+    /// spawn, in that order.
+    fn pass_turn(&mut self) {
         self.turn += 1;
+        while let Some((coord, key)) = self.regrowth.pop_due(&self.config, self.turn) {
+            self.points.restore(coord.x as usize, coord.y as usize);
+            self.hash ^= key;
+        }
+        while let Some(&spawn) = self.next_spawn() {
+            if self
+                .points
+                .consumed()
+                .contains(self.points.cell(spawn.x, spawn.y))
+            {
+                let coord = Coord::new(spawn.x as i32, spawn.y as i32);
+                self.hash ^= self.regrowth.release(&self.config, &coord);
+            }
+            self.points.set(spawn.x, spawn.y, spawn.value);
+            self.next_spawn += 1;
+        }
     }
 
-    fn evaluate_score(&mut self) {
-        self.evaluated_score = self.game_score;
+    /// The spawn due at the start of this turn, if any is left.
+    fn next_spawn(&self) -> Option<&Spawn> {
+        self.spawns
+            .get(self.next_spawn)
+            .filter(|spawn| spawn.turn == self.turn)
     }
 
-    #[allow(dead_code)]
-    fn to_string(&self) {
-        println!("turn: {}", self.turn);
-        println!("score: {}", self.game_score);
-        for i in 0..W {
-            for j in 0..H {
-                if i == self.character.x && j == self.character.y {
-                    print!("@");
-                } else if self.points[i as usize][j as usize] > 0 {
-                    print!("{}", self.points[i as usize][j as usize]);
-                } else {
-                    print!(".");
-                }
-            }
-            println!();
+    /// Whether points can come back or appear during the game.
+    fn is_dynamic(&self) -> bool {
+        self.regrowth.delay.is_some() || !self.spawns.is_empty()
+    }
+
+    fn evaluate_score(&mut self, evaluator: &Evaluator) {
+        self.evaluated_score = evaluator.evaluate(
+            self,
+            &self.characters,
+            self.remaining_turns(),
+            self.game_score,
+            |x, y| self.points.get(x, y),
+        );
+    }
+
+    /// The board as drawn by `to_string`, with `actions` named under the score.
+    fn frame(&self, actions: &[usize]) -> Frame {
+        let mut notes = Vec::new();
+        if let Some(spawn) = self.spawns.get(self.next_spawn) {
+            notes.push(format!(
+                "next spawn: {} at ({}, {}) on turn {}",
+                spawn.value, spawn.x, spawn.y, spawn.turn
+            ));
+        }
+        if !actions.is_empty() {
+            let names: Vec<&str> = actions
+                .iter()
+                .map(|&action| DIRECTION_NAMES.get(action).copied().unwrap_or("STAY"))
+                .collect();
+            notes.push(format!("actions: {}", names.join(" ")));
+        }
+        let cells = (0..self.config.h as i32)
+            .map(|i| {
+                (0..self.config.w as i32)
+                    .map(|j| {
+                        let coord = Coord::new(i, j);
+                        if let Some(index) = self.characters.iter().position(|c| *c == coord) {
+                            let label = if self.characters.len() == 1 {
+                                '@'
+                            } else {
+                                (b'A' + (index % 26) as u8) as char
+                            };
+                            Cell::Character {
+                                label,
+                                player: index,
+                            }
+                        } else if self.is_wall(i, j) {
+                            Cell::Wall
+                        } else if self.points.get(i as usize, j as usize) > 0 {
+                            Cell::Points(self.points.get(i as usize, j as usize))
+                        } else if self.regrowth.pending.iter().any(|(_, c)| *c == coord) {
+                            Cell::Regrowing
+                        } else {
+                            Cell::Empty
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Frame {
+            turn: self.turn as usize,
+            scores: vec![self.game_score as i64],
+            notes,
+            cells,
         }
-        println!();
     }
 }
 
+impl fmt::Display for MazeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.frame(&[]))
+    }
+}
+
+/// How `evaluate_score` values a state. Scores are scaled by [`EVAL_SCALE`]
+/// so that fractional bonuses survive in the integer `evaluated_score`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Evaluator {
+    /// Points collected so far.
+    GameScore,
+    /// Game score plus the points within `radius` steps, each discounted by
+    /// `decay` per step needed to reach it.
+    Discounted { decay: f64, radius: usize },
+    /// Game score minus the BFS distance to the nearest point within `radius`.
+    Nearest { radius: usize },
+    /// Game score plus `value_weight` times the discounted points minus
+    /// `distance_weight` times the distance to the nearest point.
+    Weighted {
+        decay: f64,
+        radius: usize,
+        value_weight: f64,
+        distance_weight: f64,
+    },
+}
+
+const EVAL_SCALE: f64 = 100.0;
+
+impl Evaluator {
+    /// Values `characters` with `game_score` collected and `remaining` turns
+    /// left, where `point(x, y)` gives the points left on a cell and walls
+    /// are taken from `state`. Distances are to the nearest character.
+    fn evaluate(
+        &self,
+        state: &MazeState,
+        characters: &[Coord],
+        remaining: usize,
+        game_score: i32,
+        point: impl Fn(usize, usize) -> usize,
+    ) -> i32 {
+        let (decay, radius) = match *self {
+            Evaluator::GameScore => return game_score * EVAL_SCALE as i32,
+            Evaluator::Discounted { decay, radius } => (decay, radius),
+            Evaluator::Nearest { radius } => (0.0, radius),
+            Evaluator::Weighted { decay, radius, .. } => (decay, radius),
+        };
+
+        // Points beyond the remaining turns can never be collected.
+        let radius = radius.min(remaining);
+        let mut value = 0.0;
+        let mut nearest = radius + 1;
+        state.visit_within(characters, radius, |x, y, distance| {
+            let point = point(x, y);
+            if distance > 0 && point > 0 {
+                value += point as f64 * decay.powi(distance as i32);
+                nearest = nearest.min(distance);
+            }
+        });
+
+        let bonus = match *self {
+            Evaluator::GameScore => 0.0,
+            Evaluator::Discounted { .. } => value,
+            Evaluator::Nearest { .. } => -(nearest as f64),
+            Evaluator::Weighted {
+                value_weight,
+                distance_weight,
+                ..
+            } => value_weight * value - distance_weight * nearest as f64,
+        };
+
+        ((game_score as f64 + bonus) * EVAL_SCALE).round() as i32
+    }
+}
+
+/// Reads the evaluation parameters appended by [`search_params`].
+fn evaluator(params: &Params) -> Result<Evaluator, SpecError> {
+    let decay = params.get("decay")?;
+    let radius = params.get("radius")?;
+    let eval: String = params.get("eval")?;
+    match eval.as_str() {
+        "score" => Ok(Evaluator::GameScore),
+        "discounted" => Ok(Evaluator::Discounted { decay, radius }),
+        "nearest" => Ok(Evaluator::Nearest { radius }),
+        "weighted" => Ok(Evaluator::Weighted {
+            decay,
+            radius,
+            value_weight: params.get("value_weight")?,
+            distance_weight: params.get("distance_weight")?,
+        }),
+        _ => Err(SpecError::InvalidValue {
+            key: String::from("eval"),
+            value: eval,
+        }),
+    }
+}
+
+/// Appends the evaluation parameters shared by every search to `params`.
+macro_rules! search_params {
+    ($($param:expr),* $(,)?) => {
+        &[
+            $($param,)*
+            ParamDef {
+                name: "eval",
+                default: Some("score"),
+                help: "evaluation: score, discounted, nearest or weighted",
+            },
+            ParamDef {
+                name: "decay",
+                default: Some("0.5"),
+                help: "discount per step of points not yet collected",
+            },
+            ParamDef {
+                name: "radius",
+                default: Some("5"),
+                help: "steps searched for points to evaluate",
+            },
+            ParamDef {
+                name: "value_weight",
+                default: Some("1.0"),
+                help: "weighted: weight of the discounted points",
+            },
+            ParamDef {
+                name: "distance_weight",
+                default: Some("1.0"),
+                help: "weighted: weight of the distance to the nearest point",
+            },
+        ]
+    };
+}
+
 #[allow(dead_code)]
 fn random_action(state: &MazeState) -> usize {
     let acts = state.legal_action();
@@ -133,15 +800,22 @@ fn random_action(state: &MazeState) -> usize {
     acts[get_random(acts.len())]
 }
 
+/// Uniformly random collision-free actions for the rest of the turn.
+fn random_joint_action(state: &MazeState) -> Vec<usize> {
+    let mut joint_actions = state.legal_joint_actions();
+
+    joint_actions.swap_remove(get_random(joint_actions.len()))
+}
+
 #[allow(dead_code)]
-fn greedy_action(state: &MazeState) -> usize {
+fn greedy_action(state: &MazeState, evaluator: &Evaluator) -> usize {
     let acts = state.legal_action();
     let mut best_action = -1_i32;
     let mut best_score = -1e9 as i32;
     for act in acts {
         let mut now = state.clone();
         now.advance(act);
-        now.evaluate_score();
+        now.evaluate_score(evaluator);
         if now.evaluated_score > best_score {
             best_score = now.evaluated_score;
             best_action = act as i32;
@@ -151,65 +825,173 @@ fn greedy_action(state: &MazeState) -> usize {
     best_action as usize
 }
 
-#[allow(dead_code)]
-fn beam_search_action(state: &MazeState, beam_width: i32, threshold: u128) -> usize {
+/// Returns the first action of a plan, or any legal action if the search found none.
+fn first_action(state: &MazeState, plan: &[usize]) -> usize {
+    plan.first()
+        .copied()
+        .unwrap_or_else(|| state.legal_action()[0])
+}
+
+/// States expanded by the searches since the counter was last reset, read by `sweep`.
+static NODES_EXPANDED: AtomicUsize = AtomicUsize::new(0);
+
+/// Expands `states` into their children, splitting them across `threads`
+/// workers. Children come back in the order of their parents whatever the
+/// scheduling, so a parallel search matches the sequential one. Returns
+/// `None` once any worker sees `time_keeper` run out.
+fn expand(
+    states: &[MazeState],
+    threads: usize,
+    evaluator: &Evaluator,
+    time_keeper: Option<&TimeKeeper>,
+) -> Option<Vec<MazeState>> {
+    let time_over = AtomicBool::new(false);
+    let worker = |chunk: &[MazeState]| {
+        let mut children = Vec::new();
+        for state in chunk {
+            if time_over.load(atomic::Ordering::Relaxed)
+                || time_keeper.is_some_and(TimeKeeper::is_time_over)
+            {
+                time_over.store(true, atomic::Ordering::Relaxed);
+                break;
+            }
+            NODES_EXPANDED.fetch_add(1, atomic::Ordering::Relaxed);
+            for act in state.legal_action() {
+                let mut next_state = state.clone();
+                next_state.advance(act);
+                next_state.evaluate_score(evaluator);
+                next_state.actions.push(act);
+                children.push(next_state);
+            }
+        }
+        children
+    };
+
+    let children = if threads <= 1 {
+        worker(states)
+    } else {
+        let worker = &worker;
+        let chunk_size = states.len().div_ceil(threads).max(1);
+        thread::scope(|scope| {
+            let handles: Vec<_> = states
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || worker(chunk)))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    };
+
+    (!time_over.into_inner()).then_some(children)
+}
+
+/// Returns the actions leading from `state` to the best state found.
+/// With `per_cell`, at most that many states per position of the character
+/// that just moved are expanded at each depth, so the beam does not collapse
+/// onto one path. Each depth moves one character, so several characters are
+/// searched one move at a time instead of over their joint actions. The
+/// states of a depth are expanded by `threads` workers.
+fn beam_search_plan(
+    state: &MazeState,
+    beam_width: i32,
+    per_cell: Option<usize>,
+    threads: usize,
+    threshold: u128,
+    evaluator: &Evaluator,
+) -> Vec<usize> {
     let mut now_beam = BinaryHeap::new();
-    let mut best_state = state.clone();
+    let mut root_state = state.clone();
+    root_state.actions.clear();
+    let mut best_state = root_state.clone();
     let time_keeper = TimeKeeper::new(threshold);
-    let mut t = true;
 
-    now_beam.push(state.clone());
+    now_beam.push(root_state);
     loop {
-        let mut next_beam = BinaryHeap::new();
-        for _ in 0..beam_width {
-            if time_keeper.is_time_over() {
-                return best_state.first_action;
-            }
-
-            if now_beam.is_empty() {
+        // States reached by several paths are expanded only once, from the best one.
+        let mut seen = HashSet::new();
+        let mut per_position = Grid::new(state.config.h, state.config.w, 0);
+        let mut selected = Vec::new();
+        while selected.len() < beam_width as usize {
+            let Some(now_state) = now_beam.pop() else {
                 break;
+            };
+            if !seen.insert(now_state.hash) {
+                continue;
             }
-
-            let now_state = now_beam.pop().unwrap();
-            let legal_actions = now_state.legal_action();
-            for act in legal_actions {
-                let mut next_state = now_state.clone();
-                next_state.advance(act);
-                next_state.evaluate_score();
-                if t {
-                    next_state.first_action = act;
+            if let Some(per_cell) = per_cell {
+                let character = now_state.last_moved();
+                let count = &mut per_position[(character.x as usize, character.y as usize)];
+                if *count == per_cell {
+                    continue;
                 }
-                next_beam.push(next_state);
+                *count += 1;
             }
-            t = false;
+            selected.push(now_state);
         }
 
-        now_beam = next_beam;
-        best_state = now_beam.peek().unwrap().clone();
+        let Some(children) = expand(&selected, threads, evaluator, Some(&time_keeper)) else {
+            return best_state.actions;
+        };
+        // Pushed one by one, as `BinaryHeap::from` would break ties differently.
+        now_beam = BinaryHeap::new();
+        for child in children {
+            now_beam.push(child);
+        }
+        // No state could move; the best of the last depth is the plan.
+        let Some(next_best) = now_beam.peek() else {
+            break;
+        };
+        best_state = next_best.clone();
 
         if best_state.is_done() {
             break;
         }
     }
 
-    best_state.first_action
+    best_state.actions
 }
 
 #[allow(dead_code)]
-fn chokudai_search_action(
+fn beam_search_action(
+    state: &MazeState,
+    beam_width: i32,
+    per_cell: Option<usize>,
+    threads: usize,
+    threshold: u128,
+    evaluator: &Evaluator,
+) -> usize {
+    first_action(
+        state,
+        &beam_search_plan(state, beam_width, per_cell, threads, threshold, evaluator),
+    )
+}
+
+/// Returns the actions leading from `state` to the best state of the deepest beam.
+/// The states of each depth are expanded by `threads` workers, which stop as
+/// soon as the time is over, even in the middle of a pass.
+fn chokudai_search_plan(
     state: &MazeState,
     beam_width: usize,
     beam_depth: usize,
+    threads: usize,
     threshold: u128,
-) -> usize {
+    evaluator: &Evaluator,
+) -> Vec<usize> {
     let time_keeper = TimeKeeper::new(threshold);
     let mut beam = vec![BinaryHeap::new(); beam_depth + 1];
-    beam[0].push(state.clone());
-    loop {
+    // Hashes of the states already expanded at each depth.
+    let mut seen = vec![HashSet::new(); beam_depth + 1];
+    let mut root_state = state.clone();
+    root_state.actions.clear();
+    beam[0].push(root_state);
+    'passes: loop {
         for t in 0..beam_depth {
             let mut now_beam = beam.get(t).unwrap().clone();
 
-            for _ in 0..beam_width {
+            let mut selected = Vec::new();
+            while selected.len() < beam_width {
                 if now_beam.is_empty() {
                     break;
                 }
@@ -218,18 +1000,18 @@ fn chokudai_search_action(
                     break;
                 }
                 now_beam.pop();
-                let legal_actions = now_state.legal_action();
-                for act in legal_actions {
-                    let mut next_state = now_state.clone();
-                    next_state.advance(act);
-                    next_state.evaluate_score();
-                    if t == 0 {
-                        next_state.first_action = act;
-                    }
-                    beam[t + 1].push(next_state);
+                if !seen[t].insert(now_state.hash) {
+                    continue;
                 }
+                selected.push(now_state);
             }
             beam[t] = now_beam;
+            let Some(children) = expand(&selected, threads, evaluator, Some(&time_keeper)) else {
+                break 'passes;
+            };
+            for child in children {
+                beam[t + 1].push(child);
+            }
         }
         if time_keeper.is_time_over() {
             break;
@@ -238,33 +1020,1372 @@ fn chokudai_search_action(
     for t in (0..beam_depth + 1).rev() {
         let now_beam = beam.get(t).unwrap();
         if !now_beam.is_empty() {
-            return now_beam.peek().unwrap().first_action;
+            return now_beam.peek().unwrap().actions.clone();
+        }
+    }
+
+    Vec::new()
+}
+
+#[allow(dead_code)]
+fn chokudai_search_action(
+    state: &MazeState,
+    beam_width: usize,
+    beam_depth: usize,
+    threads: usize,
+    threshold: u128,
+    evaluator: &Evaluator,
+) -> usize {
+    first_action(
+        state,
+        &chokudai_search_plan(state, beam_width, beam_depth, threads, threshold, evaluator),
+    )
+}
+
+/// Beam search that keeps only the action and parent of every node.
+///
+/// A node's state is its root state plus the cells it has collected, so the
+/// beam holds a position, a score and a bitset per entry instead of a full
+/// point grid, and candidates get their bitset only once they are selected.
+/// Regrowth and spawns of the root's cells are followed by a single copy of
+/// the root shared by the whole beam. Candidates are evaluated before the
+/// events of the turn they end, which only the bonus evaluators notice.
+mod lean_beam {
+    use super::{Coord, Evaluator, MazeState, Regrowth, TimeKeeper, NODES_EXPANDED};
+    use common::grid::{CellSet, Grid};
+    use std::collections::{HashSet, VecDeque};
+    use std::sync::atomic;
+
+    /// Arena entry, enough to rebuild the actions leading to a node.
+    struct Node {
+        parent: u32,
+        action: u8,
+    }
+
+    struct Entry {
+        node: u32,
+        characters: Vec<Coord>,
+        game_score: i32,
+        hash: u64,
+        collected: CellSet,
+        regrowth: Regrowth,
+    }
+
+    struct Candidate {
+        entry: usize,
+        action: u8,
+        character: Coord,
+        game_score: i32,
+        evaluated_score: i32,
+        hash: u64,
+        collects: bool,
+    }
+
+    fn plan(arena: &[Node], mut node: u32) -> Vec<usize> {
+        let mut actions = Vec::new();
+        while node != 0 {
+            actions.push(arena[node as usize].action as usize);
+            node = arena[node as usize].parent;
+        }
+        actions.reverse();
+
+        actions
+    }
+
+    /// Returns the actions leading from `state` to the best state found,
+    /// keeping at most `per_cell` entries per character position if given.
+    pub fn lean_beam_search_plan(
+        state: &MazeState,
+        beam_width: usize,
+        per_cell: Option<usize>,
+        threshold: u128,
+        evaluator: &Evaluator,
+    ) -> Vec<usize> {
+        let config = &state.config;
+        let cell = |coord: &Coord| state.points.cell(coord.x as usize, coord.y as usize);
+        let time_keeper = TimeKeeper::new(threshold);
+
+        let mut arena = vec![Node {
+            parent: 0,
+            action: 0,
+        }];
+        let mut beam = vec![Entry {
+            node: 0,
+            characters: state.characters.clone(),
+            game_score: state.game_score,
+            hash: state.hash,
+            collected: CellSet::new(config.h * config.w),
+            regrowth: Regrowth {
+                delay: state.regrowth.delay,
+                pending: VecDeque::new(),
+            },
+        }];
+        // The root with only the scheduled events applied.
+        let mut background = state.clone();
+        let character_n = state.characters.len();
+        for depth in 1..=state.remaining_steps() {
+            // Every entry of a depth waits on the same character.
+            let mover = (state.mover + depth - 1) % character_n;
+            let mover_keys =
+                MazeState::mover_key(mover) ^ MazeState::mover_key((mover + 1) % character_n);
+            let remaining = (state.remaining_steps() - depth).div_ceil(character_n);
+            let mut candidates = Vec::new();
+            for (i, entry) in beam.iter().enumerate() {
+                if time_keeper.is_time_over() {
+                    return plan(&arena, beam[0].node);
+                }
+                NODES_EXPANDED.fetch_add(1, atomic::Ordering::Relaxed);
+                let from = &entry.characters[mover];
+                // Positions after the move, for the evaluator.
+                let mut characters = entry.characters.clone();
+                for action in state.legal_moves(&entry.characters, mover) {
+                    let character = Coord::new(
+                        from.x + MazeState::dx[action],
+                        from.y + MazeState::dy[action],
+                    );
+                    let mut hash = entry.hash
+                        ^ MazeState::character_key(config, mover, from)
+                        ^ MazeState::character_key(config, mover, &character)
+                        ^ mover_keys;
+                    let point = background
+                        .points
+                        .get(character.x as usize, character.y as usize)
+                        as i32;
+                    let collects = point > 0 && !entry.collected.contains(cell(&character));
+                    let mut game_score = entry.game_score;
+                    if collects {
+                        game_score += point;
+                        hash ^= entry.regrowth.key(config, background.turn, &character);
+                    }
+                    let collected = &entry.collected;
+                    characters[mover] = character.clone();
+                    let evaluated_score =
+                        evaluator.evaluate(state, &characters, remaining, game_score, |x, y| {
+                            if collected.contains(state.points.cell(x, y)) {
+                                0
+                            } else {
+                                background.points.get(x, y)
+                            }
+                        });
+                    candidates.push(Candidate {
+                        entry: i,
+                        action: action as u8,
+                        character,
+                        game_score,
+                        evaluated_score,
+                        hash,
+                        collects,
+                    });
+                }
+            }
+
+            // Stable, so ties keep the order of their parents in the beam.
+            candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.evaluated_score));
+            let mut seen = HashSet::new();
+            let mut per_position = Grid::new(config.h, config.w, 0);
+            let mut next_beam = Vec::with_capacity(beam_width);
+            for candidate in candidates {
+                if next_beam.len() == beam_width {
+                    break;
+                }
+                if !seen.insert(candidate.hash) {
+                    continue;
+                }
+                if let Some(per_cell) = per_cell {
+                    let character = &candidate.character;
+                    let count = &mut per_position[(character.x as usize, character.y as usize)];
+                    if *count == per_cell {
+                        continue;
+                    }
+                    *count += 1;
+                }
+                let parent = &beam[candidate.entry];
+                let mut collected = parent.collected.clone();
+                let mut regrowth = parent.regrowth.clone();
+                if candidate.collects {
+                    collected.insert(cell(&candidate.character));
+                    regrowth.collect(config, background.turn, &candidate.character);
+                }
+                let mut characters = parent.characters.clone();
+                characters[mover] = candidate.character;
+                arena.push(Node {
+                    parent: parent.node,
+                    action: candidate.action,
+                });
+                next_beam.push(Entry {
+                    node: (arena.len() - 1) as u32,
+                    characters,
+                    game_score: candidate.game_score,
+                    hash: candidate.hash,
+                    collected,
+                    regrowth,
+                });
+            }
+            // No entry could move; the best of the last depth is the plan.
+            if next_beam.is_empty() {
+                break;
+            }
+            if mover + 1 == character_n {
+                let first_spawn = background.next_spawn;
+                background.pass_turn();
+                let spawns = &background.spawns[first_spawn..background.next_spawn];
+                for entry in &mut next_beam {
+                    while let Some((coord, key)) = entry.regrowth.pop_due(config, background.turn) {
+                        entry.collected.remove(cell(&coord));
+                        entry.hash ^= key;
+                    }
+                    for spawn in spawns {
+                        let coord = Coord::new(spawn.x as i32, spawn.y as i32);
+                        if entry.collected.remove(cell(&coord)) {
+                            entry.hash ^= entry.regrowth.release(config, &coord);
+                        }
+                    }
+                }
+            }
+            beam = next_beam;
         }
+
+        plan(&arena, beam[0].node)
     }
+}
 
-    0
+/// Exact solver for small mazes: depth-first branch and bound over the
+/// actions, used as ground truth for the heuristic searches.
+mod exact {
+    use super::{splitmix64, MazeState};
+    use std::cmp::Reverse;
+    use std::collections::HashMap;
+
+    struct Solver {
+        best_score: i32,
+        best_plan: Vec<usize>,
+        plan: Vec<usize>,
+        // Most remaining steps and best score each state has been searched
+        // with. A state reached again with no more steps left and no higher
+        // score cannot do better. Keys of dynamic mazes include the turn, as
+        // their future depends on it, and regrown cells leave the hash
+        // without taking their points back from the score.
+        searched: HashMap<u64, (usize, i32)>,
+        nodes: usize,
+    }
+
+    /// Each remaining step collects at most one cell, and only cells within
+    /// the remaining turns of some character can be reached. A regrowing
+    /// cell counts its full points once per regrowth that fits in the
+    /// remaining turns, and every spawn still to come counts wherever it is.
+    fn upper_bound(state: &MazeState) -> i32 {
+        let remaining = state.remaining_steps();
+        let mut points = Vec::new();
+        let radius = state.remaining_turns();
+        let copies = state.regrowth.delay.map_or(1, |delay| 1 + radius / delay);
+        state.visit_within(&state.characters, radius, |x, y, _| {
+            let point = match state.regrowth.delay {
+                Some(_) => state.points.initial()[(x, y)],
+                None => state.points.get(x, y),
+            };
+            // Cells under characters count too: they regrow, or are left
+            // for another character.
+            if point > 0 {
+                points.extend(std::iter::repeat_n(point as i32, copies));
+            }
+        });
+        for spawn in &state.spawns[state.next_spawn..] {
+            points.extend(std::iter::repeat_n(spawn.value as i32, copies));
+        }
+        points.sort_unstable_by_key(|&point| Reverse(point));
+
+        state.game_score + points.iter().take(remaining).sum::<i32>()
+    }
+
+    impl Solver {
+        fn search(&mut self, state: &MazeState) {
+            self.nodes += 1;
+            if state.is_done() {
+                if state.game_score > self.best_score {
+                    self.best_score = state.game_score;
+                    self.best_plan = self.plan.clone();
+                }
+                return;
+            }
+            if upper_bound(state) <= self.best_score {
+                return;
+            }
+            let remaining = state.remaining_steps();
+            let key = if state.is_dynamic() {
+                state.hash ^ splitmix64(state.turn as u64)
+            } else {
+                state.hash
+            };
+            if self.searched.get(&key).is_some_and(|&(searched, score)| {
+                searched >= remaining && score >= state.game_score
+            }) {
+                return;
+            }
+            self.searched.insert(key, (remaining, state.game_score));
+
+            let mut children: Vec<(usize, MazeState)> = state
+                .legal_action()
+                .into_iter()
+                .map(|act| {
+                    let mut child = state.clone();
+                    child.advance(act);
+                    (act, child)
+                })
+                .collect();
+            // Collecting first finds a good incumbent early.
+            children.sort_by_key(|(_, child)| Reverse(child.game_score));
+            for (act, child) in children {
+                self.plan.push(act);
+                self.search(&child);
+                self.plan.pop();
+            }
+        }
+    }
+
+    /// Returns the best final score reachable from `state`, a plan reaching
+    /// it and the number of nodes searched.
+    pub fn solve(state: &MazeState) -> (i32, Vec<usize>, usize) {
+        let mut solver = Solver {
+            best_score: -1,
+            best_plan: Vec::new(),
+            plan: Vec::new(),
+            searched: HashMap::new(),
+            nodes: 0,
+        };
+        solver.search(state);
+
+        (solver.best_score, solver.best_plan, solver.nodes)
+    }
 }
 
-fn play_game() -> i32 {
-    let mut state = MazeState::new();
-    // state.to_string();
+/// Nested searches over random playouts to the end of the game, scored by
+/// the final game score. Both return the best complete plan found, so they
+/// stop early with a usable plan once `threshold` milliseconds have passed.
+mod nested {
+    use super::{random_action, MazeState, TimeKeeper};
+    use common::get_random;
+
+    /// Plays `choose(state)` until the game ends and returns the final score
+    /// and the actions played.
+    fn playout(
+        state: &MazeState,
+        mut choose: impl FnMut(&MazeState) -> usize,
+    ) -> (i32, Vec<usize>) {
+        let mut state = state.clone();
+        let mut actions = Vec::with_capacity(state.remaining_steps());
+        while !state.is_done() {
+            let action = choose(&state);
+            state.advance(action);
+            actions.push(action);
+        }
+
+        (state.game_score, actions)
+    }
+
+    /// Level-`level` Nested Monte Carlo Search: every legal action is scored
+    /// by a search one level lower, and the best sequence found so far is
+    /// followed, so an improvement found deep in the game is never lost.
+    fn nmcs(state: &MazeState, level: usize, time_keeper: &TimeKeeper) -> (i32, Vec<usize>) {
+        if level == 0 {
+            return playout(state, random_action);
+        }
+        let mut state = state.clone();
+        // A finished game is its own best sequence.
+        let mut best_score = if state.is_done() {
+            state.game_score
+        } else {
+            i32::MIN
+        };
+        let mut best_plan = Vec::new();
+        let mut played = 0;
+        while !state.is_done() {
+            for action in state.legal_action() {
+                let mut child = state.clone();
+                child.advance(action);
+                let (score, rest) = nmcs(&child, level - 1, time_keeper);
+                if score > best_score {
+                    best_score = score;
+                    best_plan.truncate(played);
+                    best_plan.push(action);
+                    best_plan.extend(rest);
+                }
+                if time_keeper.is_time_over() {
+                    return (best_score, best_plan);
+                }
+            }
+            state.advance(best_plan[played]);
+            played += 1;
+        }
+
+        (best_score, best_plan)
+    }
+
+    /// Returns the best plan of a level-`level` Nested Monte Carlo Search.
+    pub fn nmcs_plan(state: &MazeState, level: usize, threshold: u128) -> Vec<usize> {
+        nmcs(state, level, &TimeKeeper::new(threshold)).1
+    }
+
+    /// Index of a move in a policy: the character, the cell it leaves and
+    /// its direction, so what is learnt on a cell holds on every turn.
+    fn code(state: &MazeState, action: usize) -> usize {
+        let character = &state.characters[state.mover];
+        let cell = state
+            .points
+            .cell(character.x as usize, character.y as usize);
+        let cells = state.config.h * state.config.w;
+
+        (state.mover * cells + cell) * MazeState::dx.len() + action
+    }
+
+    /// Draws a legal action with probability proportional to the exponent
+    /// of its weight.
+    fn sample(state: &MazeState, policy: &[f64]) -> usize {
+        const RESOLUTION: usize = 1 << 24;
+        let actions = state.legal_action();
+        let weights: Vec<f64> = actions
+            .iter()
+            .map(|&action| policy[code(state, action)].exp())
+            .collect();
+        let mut target =
+            get_random(RESOLUTION) as f64 / RESOLUTION as f64 * weights.iter().sum::<f64>();
+        for (&action, weight) in actions.iter().zip(weights) {
+            if target < weight {
+                return action;
+            }
+            target -= weight;
+        }
+
+        *actions.last().unwrap()
+    }
+
+    /// Moves `policy` towards `plan` by `alpha`, taking from every legal
+    /// action along the way in proportion to its probability.
+    fn adapt(policy: &[f64], state: &MazeState, plan: &[usize], alpha: f64) -> Vec<f64> {
+        let mut adapted = policy.to_vec();
+        let mut state = state.clone();
+        for &action in plan {
+            let actions = state.legal_action();
+            let total: f64 = actions
+                .iter()
+                .map(|&legal| policy[code(&state, legal)].exp())
+                .sum();
+            adapted[code(&state, action)] += alpha;
+            for legal in actions {
+                let code = code(&state, legal);
+                adapted[code] -= alpha * policy[code].exp() / total;
+            }
+            state.advance(action);
+        }
+
+        adapted
+    }
+
+    /// Nested Rollout Policy Adaptation: each level runs `iterations`
+    /// searches one level lower and adapts a copy of the policy towards the
+    /// best sequence so far; level 0 is a playout drawn from the policy.
+    fn nrpa(
+        state: &MazeState,
+        level: usize,
+        iterations: usize,
+        alpha: f64,
+        policy: &[f64],
+        time_keeper: &TimeKeeper,
+    ) -> (i32, Vec<usize>) {
+        if level == 0 {
+            return playout(state, |state| sample(state, policy));
+        }
+        let mut policy = policy.to_vec();
+        let mut best_score = i32::MIN;
+        let mut best_plan = Vec::new();
+        for _ in 0..iterations {
+            let (score, plan) = nrpa(state, level - 1, iterations, alpha, &policy, time_keeper);
+            if score >= best_score {
+                best_score = score;
+                best_plan = plan;
+            }
+            if time_keeper.is_time_over() {
+                break;
+            }
+            policy = adapt(&policy, state, &best_plan, alpha);
+        }
+
+        (best_score, best_plan)
+    }
+
+    /// Returns the best plan of a level-`level` NRPA from a uniform policy.
+    pub fn nrpa_plan(
+        state: &MazeState,
+        level: usize,
+        iterations: usize,
+        alpha: f64,
+        threshold: u128,
+    ) -> Vec<usize> {
+        let codes = state.characters.len() * state.config.h * state.config.w * MazeState::dx.len();
+        let time_keeper = TimeKeeper::new(threshold);
+
+        nrpa(
+            state,
+            level,
+            iterations,
+            alpha,
+            &vec![0.0; codes],
+            &time_keeper,
+        )
+        .1
+    }
+}
+
+/// Follows the plans of an AI, searching again every `interval` actions or
+/// when the plan runs out. An interval of 1 searches before every action.
+struct Planner {
+    interval: usize,
+    plan: Vec<usize>,
+    next: usize,
+}
+
+impl Planner {
+    fn new(interval: usize) -> Planner {
+        Planner {
+            interval,
+            plan: Vec::new(),
+            next: 0,
+        }
+    }
+
+    fn next_action(&mut self, ai: &Ai, state: &MazeState) -> usize {
+        if self.next == self.plan.len() || self.next == self.interval {
+            self.plan = ai.1(state);
+            if self.plan.is_empty() {
+                self.plan.push(first_action(state, &self.plan));
+            }
+            self.next = 0;
+        }
+        self.next += 1;
+
+        self.plan[self.next - 1]
+    }
+}
+
+fn play_game(config: GameConfig, rules: MazeRules, ai: &Ai, replan_interval: usize) -> i32 {
+    let mut state = MazeState::new(config, rules);
+    let mut planner = Planner::new(replan_interval);
+    // println!("{}", state);
     while !state.is_done() {
-        state.advance(chokudai_search_action(&state, 1, END_TURN as usize, 1));
-        // state.to_string();
+        state.advance(planner.next_action(ai, &state));
+        // println!("{}", state);
     }
     state.game_score
 }
 
-fn test_ai_score(game_number: usize) {
+fn test_ai_score(
+    config: GameConfig,
+    rules: MazeRules,
+    ai: &Ai,
+    replan_interval: usize,
+    seeds: Range<u64>,
+    format: OutputFormat,
+) -> f64 {
+    let game_number = seeds.end - seeds.start;
+    let mut score_mean = 0.0;
+    for seed in seeds {
+        init_random_generator(seed);
+        let score = play_game(config, rules, ai, replan_interval);
+        if format == OutputFormat::Csv {
+            println!("{},{},{}", csv_field(&ai.0), seed, score);
+        }
+        score_mean += score as f64;
+    }
+    score_mean /= game_number as f64;
+    if format == OutputFormat::Text {
+        println!("Score of {}: {:.2}", ai.0, score_mean);
+    }
+
+    score_mean
+}
+
+fn get_sample_states(config: GameConfig, rules: MazeRules, game_number: usize) -> Vec<MazeState> {
+    init_random_generator(0);
+
+    let mut states = Vec::new();
+    for _ in 0..game_number {
+        let mut state = MazeState::new(config, rules);
+        let turn = get_random(config.end_turn);
+        for _ in 0..turn * config.character_n {
+            state.advance(random_action(&state));
+        }
+        states.push(state);
+    }
+
+    states
+}
+
+/// Prints each AI's score against the optimal score on every seed, and the
+/// mean optimality gap in percent.
+fn report_optimality_gap(
+    config: GameConfig,
+    rules: MazeRules,
+    ais: &[Ai],
+    seeds: Range<u64>,
+    format: OutputFormat,
+) {
+    let game_number = seeds.end - seeds.start;
+    let mut gap_sums = vec![0.0; ais.len()];
+    let mut optimal_counts = vec![0; ais.len()];
+    for seed in seeds {
+        init_random_generator(seed);
+        let (optimal, _, nodes) = exact::solve(&MazeState::new(config, rules));
+        if format == OutputFormat::Text {
+            println!("seed {}: optimal {} ({} nodes)", seed, optimal, nodes);
+        }
+        for (i, ai) in ais.iter().enumerate() {
+            init_random_generator(seed);
+            let score = play_game(config, rules, ai, 1);
+            let gap = if optimal > 0 {
+                (optimal - score) as f64 / optimal as f64 * 100.0
+            } else {
+                0.0
+            };
+            gap_sums[i] += gap;
+            if score == optimal {
+                optimal_counts[i] += 1;
+            }
+            match format {
+                OutputFormat::Text => println!("    {}: {} (gap {:.2}%)", ai.0, score, gap),
+                OutputFormat::Csv => {
+                    println!(
+                        "{},{},{},{},{:.4}",
+                        seed,
+                        csv_field(&ai.0),
+                        score,
+                        optimal,
+                        gap
+                    )
+                }
+            }
+        }
+    }
+    if format == OutputFormat::Text {
+        for (i, ai) in ais.iter().enumerate() {
+            println!(
+                "Mean gap of {}: {:.2}% (optimal on {}/{} seeds)",
+                ai.0,
+                gap_sums[i] / game_number as f64,
+                optimal_counts[i],
+                game_number
+            );
+        }
+    }
+}
+
+/// One search configuration of a `sweep`.
+struct SweepPoint {
+    search: String,
+    width: usize,
+    depth: Option<usize>,
+    ms: u128,
+}
+
+impl SweepPoint {
+    fn spec(&self) -> String {
+        match self.depth {
+            Some(depth) => format!(
+                "{}:width={},depth={},ms={}",
+                self.search, self.width, depth, self.ms
+            ),
+            None => format!("{}:width={},ms={}", self.search, self.width, self.ms),
+        }
+    }
+}
+
+/// Prints the score distribution of one sweep point over the seeds and the
+/// mean number of states it expanded per game.
+fn sweep_point(
+    config: GameConfig,
+    rules: MazeRules,
+    point: &SweepPoint,
+    seeds: Range<u64>,
+    format: OutputFormat,
+) -> Result<(), SpecError> {
+    let ai = build_ai(&point.spec())?;
+    NODES_EXPANDED.store(0, atomic::Ordering::Relaxed);
+    let scores: Vec<f64> = seeds
+        .map(|seed| {
+            init_random_generator(seed);
+            play_game(config, rules, &ai, 1) as f64
+        })
+        .collect();
+    let nodes = NODES_EXPANDED.load(atomic::Ordering::Relaxed) as f64 / scores.len() as f64;
+
+    let games = scores.len() as f64;
+    let mean = scores.iter().sum::<f64>() / games;
+    let stddev = (scores
+        .iter()
+        .map(|score| (score - mean).powi(2))
+        .sum::<f64>()
+        / games)
+        .sqrt();
+    let min = scores.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    match format {
+        OutputFormat::Text => println!(
+            "{}: mean {:.2}, stddev {:.2}, min {}, max {}, {:.0} nodes per game",
+            ai.0, mean, stddev, min, max, nodes
+        ),
+        OutputFormat::Csv => println!(
+            "{},{},{},{},{},{:.2},{:.2},{},{},{:.0}",
+            point.search,
+            point.width,
+            point
+                .depth
+                .map(|depth| depth.to_string())
+                .unwrap_or_default(),
+            point.ms,
+            games,
+            mean,
+            stddev,
+            min,
+            max,
+            nodes
+        ),
+    }
+
+    Ok(())
+}
+
+/// Prints the mean score and the total time of `ai` when it searches again every `interval` turns.
+fn compare_replan_interval(
+    config: GameConfig,
+    rules: MazeRules,
+    ai: &Ai,
+    interval: usize,
+    seeds: Range<u64>,
+    format: OutputFormat,
+) {
+    let game_number = seeds.end - seeds.start;
+    let start_time = Instant::now();
     let mut score_mean = 0.0;
-    for i in 0..game_number {
-        init_random_generator(i as u64);
-        score_mean += play_game() as f64;
+    for seed in seeds {
+        init_random_generator(seed);
+        score_mean += play_game(config, rules, ai, interval) as f64;
     }
     score_mean /= game_number as f64;
-    println!("Score: {:.2}", score_mean);
+    let diff = Instant::now() - start_time;
+    match format {
+        OutputFormat::Text => println!(
+            "{} replanning every {} turns: score {:.2} in {} ms",
+            ai.0,
+            interval,
+            score_mean,
+            diff.as_millis()
+        ),
+        OutputFormat::Csv => println!(
+            "{},{},{:.2},{}",
+            csv_field(&ai.0),
+            interval,
+            score_mean,
+            diff.as_millis()
+        ),
+    }
+}
+
+fn calc_execution_speed(ai: &Ai, states: &[MazeState], format: OutputFormat) {
+    let start_time = Instant::now();
+    for state in states {
+        ai.1(state);
+    }
+    let diff = Instant::now() - start_time;
+    match format {
+        OutputFormat::Text => println!(
+            "{} take {} ms to process {} nodes",
+            ai.0,
+            diff.as_millis(),
+            states.len()
+        ),
+        OutputFormat::Csv => println!("{},{},{}", csv_field(&ai.0), states.len(), diff.as_millis()),
+    }
+}
+
+/// Prints the state after a turn; the actions of several characters are
+/// separated by spaces.
+fn print_turn(state: &MazeState, actions: &[usize], format: OutputFormat) {
+    match format {
+        OutputFormat::Text => println!("{}", state),
+        OutputFormat::Csv => {
+            let actions: Vec<String> = actions.iter().map(usize::to_string).collect();
+            println!("{},{},{}", state.turn, actions.join(" "), state.game_score);
+        }
+    }
+}
+
+type Action = Box<dyn Fn(&MazeState) -> Vec<usize>>;
+
+fn ai_registry() -> Registry<Action> {
+    Registry::new(vec![
+        Algorithm {
+            name: "random",
+            help: "uniformly random legal joint action",
+            params: &[],
+            build: |_| Ok(Box::new(random_joint_action)),
+        },
+        Algorithm {
+            name: "greedy",
+            help: "best evaluated score after one action",
+            params: search_params![],
+            build: |params| {
+                let evaluator = evaluator(params)?;
+                Ok(Box::new(move |state| {
+                    vec![greedy_action(state, &evaluator)]
+                }))
+            },
+        },
+        Algorithm {
+            name: "exact",
+            help: "optimal plan by branch and bound; small boards only",
+            params: &[],
+            build: |_| Ok(Box::new(|state| exact::solve(state).1)),
+        },
+        Algorithm {
+            name: "nmcs",
+            help: "nested Monte Carlo search over random playouts",
+            params: &[
+                ParamDef {
+                    name: "level",
+                    default: Some("1"),
+                    help: "nesting level; level 0 is a single random playout",
+                },
+                ParamDef {
+                    name: "ms",
+                    default: None,
+                    help: "time threshold in milliseconds (default: none)",
+                },
+            ],
+            build: |params| {
+                let level: usize = params.get("level")?;
+                let threshold = params.get_opt("ms")?.unwrap_or(u128::MAX);
+                Ok(Box::new(move |state| {
+                    nested::nmcs_plan(state, level, threshold)
+                }))
+            },
+        },
+        Algorithm {
+            name: "nrpa",
+            help: "nested rollout policy adaptation",
+            params: &[
+                ParamDef {
+                    name: "level",
+                    default: Some("2"),
+                    help: "nesting level; level 0 is a single playout",
+                },
+                ParamDef {
+                    name: "iterations",
+                    default: Some("20"),
+                    help: "searches one level lower per level",
+                },
+                ParamDef {
+                    name: "alpha",
+                    default: Some("1.0"),
+                    help: "policy learning rate",
+                },
+                ParamDef {
+                    name: "ms",
+                    default: None,
+                    help: "time threshold in milliseconds (default: none)",
+                },
+            ],
+            build: |params| {
+                let level: usize = params.get("level")?;
+                let iterations: usize = params.get("iterations")?;
+                let alpha: f64 = params.get("alpha")?;
+                let threshold = params.get_opt("ms")?.unwrap_or(u128::MAX);
+                Ok(Box::new(move |state| {
+                    nested::nrpa_plan(state, level, iterations, alpha, threshold)
+                }))
+            },
+        },
+        Algorithm {
+            name: "beam",
+            help: "beam search with a time threshold",
+            params: search_params![
+                ParamDef {
+                    name: "width",
+                    default: Some("2"),
+                    help: "beam width",
+                },
+                ParamDef {
+                    name: "per_cell",
+                    default: None,
+                    help: "most entries per character position and depth (default: no cap)",
+                },
+                ParamDef {
+                    name: "threads",
+                    default: Some("1"),
+                    help: "worker threads expanding each depth",
+                },
+                ParamDef {
+                    name: "ms",
+                    default: Some("1"),
+                    help: "time threshold in milliseconds",
+                },
+            ],
+            build: |params| {
+                let beam_width: i32 = params.get_at_least("width", 1)?;
+                let per_cell: Option<usize> = params.get_opt_at_least("per_cell", 1)?;
+                let threads: usize = params.get("threads")?;
+                let threshold: u128 = params.get("ms")?;
+                let evaluator = evaluator(params)?;
+                Ok(Box::new(move |state| {
+                    beam_search_plan(state, beam_width, per_cell, threads, threshold, &evaluator)
+                }))
+            },
+        },
+        Algorithm {
+            name: "lean_beam",
+            help: "beam search over parent pointers instead of cloned states",
+            params: search_params![
+                ParamDef {
+                    name: "width",
+                    default: Some("2"),
+                    help: "beam width",
+                },
+                ParamDef {
+                    name: "per_cell",
+                    default: None,
+                    help: "most entries per character position and depth (default: no cap)",
+                },
+                ParamDef {
+                    name: "ms",
+                    default: Some("1"),
+                    help: "time threshold in milliseconds",
+                },
+            ],
+            build: |params| {
+                let beam_width: usize = params.get_at_least("width", 1)?;
+                let per_cell: Option<usize> = params.get_opt_at_least("per_cell", 1)?;
+                let threshold: u128 = params.get("ms")?;
+                let evaluator = evaluator(params)?;
+                Ok(Box::new(move |state| {
+                    lean_beam::lean_beam_search_plan(
+                        state, beam_width, per_cell, threshold, &evaluator,
+                    )
+                }))
+            },
+        },
+        Algorithm {
+            name: "chokudai",
+            help: "chokudai search with a time threshold",
+            params: search_params![
+                ParamDef {
+                    name: "width",
+                    default: Some("1"),
+                    help: "beam width per depth",
+                },
+                ParamDef {
+                    name: "depth",
+                    default: None,
+                    help: "beam depth (default: remaining moves)",
+                },
+                ParamDef {
+                    name: "threads",
+                    default: Some("1"),
+                    help: "worker threads expanding each depth",
+                },
+                ParamDef {
+                    name: "ms",
+                    default: Some("1"),
+                    help: "time threshold in milliseconds",
+                },
+            ],
+            build: |params| {
+                let beam_width: usize = params.get_at_least("width", 1)?;
+                let beam_depth: Option<usize> = params.get_opt("depth")?;
+                let threads: usize = params.get("threads")?;
+                let threshold: u128 = params.get("ms")?;
+                let evaluator = evaluator(params)?;
+                Ok(Box::new(move |state| {
+                    let beam_depth = beam_depth.unwrap_or(state.remaining_steps());
+                    chokudai_search_plan(
+                        state, beam_width, beam_depth, threads, threshold, &evaluator,
+                    )
+                }))
+            },
+        },
+    ])
+}
+
+fn build_ai(spec: &str) -> Result<Ai, SpecError> {
+    let spec: AiSpec = spec.parse()?;
+    let action = ai_registry().build(&spec)?;
+
+    Ok(Ai(spec.to_string(), action))
+}
+
+const USAGE: &str = "\
+usage: ch03 [COMMAND] [OPTIONS]
+
+commands:
+    help        print this message
+    list        print the available AIs and their parameters
+    play        play one game and print every turn
+    match       print the mean score of each AI over the seeds (default)
+    bench       time each AI on states sampled from random play
+    throughput  time cloning and advancing sampled states
+    replay      print every turn of a recorded game
+    replan      compare mean score and time when searching every K actions
+    sweep       score statistics and nodes expanded over a grid of search settings
+    gap         compare each AI with the exact optimum on small boards (default 5x5, 12 turns)
+    tune        search the parameters of the first AI for the best mean score
+
+options:
+    --ai SPEC                 AI such as `beam:width=5,ms=2`; repeatable (default chokudai)
+    --height N, --width N     board size (default 30x30)
+    --end-turn N              turn limit (default 100)
+    --characters N            characters moved one after another each turn (default 1)
+    --walls                   generate impassable cells, keeping every open cell reachable
+    --regrow N                collected cells get their points back after N turns
+    --spawn-every N           1 to 9 points appear every N turns on a cell drawn from the board
+    --seed N                  play: seed of the board (default 0)
+    --seeds A..B, --games N   match: seeds to play (default 0..100, 0..20 for replan, gap, sweep, tune)
+    --games N                 bench, throughput: number of sampled states (default 100)
+    --rounds N                throughput: times each state is cloned and advanced (default 1000)
+    --replan K                play, match: search again every K actions (default 1)
+    --intervals K,...         replan: intervals to compare (default 1,10,100)
+    --search NAME,...         sweep: beam, lean_beam and/or chokudai (default beam,chokudai)
+    --ms MS,...               sweep: time thresholds (default 1,2,5,10)
+    --widths N,...            sweep: beam widths (default 1,2,5,10)
+    --depths N,...            sweep: chokudai depths (default remaining moves)
+    --param NAME=LOW..HIGH    tune: range of a parameter, integer if both bounds are; repeatable
+    --samples N               tune: random candidates before SPSA refines the best (default 20)
+    --iterations N            tune: SPSA steps of two candidates each (default 20)
+    --tune-seed N             tune: seed of the candidates, apart from the game seeds (default 0)
+    --out FILE                tune: write the best spec to FILE
+    --board FILE              play: start from a board file
+    --record FILE             play: save the game; replay: game to print, under the same rules
+    --states FILE             replay: write every turn to FILE as one line
+    --animate                 replay: redraw the board in place every turn, controlled from stdin
+    --delay MS                replay: time each turn stays on screen when animating (default 300)
+    --no-color                replay: animate without colors, as does setting NO_COLOR
+    --format text|csv         output format (default text)";
+
+/// Reads `--replan K`, the number of planned actions followed before searching again.
+fn take_replan_interval(args: &mut Args) -> Result<usize, CliError> {
+    match args.take_all("replan")?.pop() {
+        Some(value) => match value.parse() {
+            Ok(interval) if interval > 0 => Ok(interval),
+            _ => Err(CliError::InvalidValue {
+                key: String::from("replan"),
+                value,
+            }),
+        },
+        None => Ok(1),
+    }
+}
+
+fn run(mut args: Args) -> Result<(), Box<dyn Error>> {
+    let command = args.command.take().unwrap_or_else(|| String::from("match"));
+    let config = args.take_config(if command == "gap" {
+        SMALL_CONFIG
+    } else {
+        DEFAULT_CONFIG
+    })?;
+    let rules = MazeRules {
+        walls: args.take_flag("walls")?,
+        regrow: args.take("regrow")?,
+        spawn_every: args.take("spawn-every")?,
+    };
+    if rules.regrow == Some(0) || rules.spawn_every == Some(0) {
+        return Err(
+            CliError::Invalid(String::from("regrow and spawn-every must be positive")).into(),
+        );
+    }
+    if config.character_n == 0 || config.character_n > config.h * config.w {
+        return Err(CliError::Invalid(String::from(
+            "characters must be between 1 and the number of cells",
+        ))
+        .into());
+    }
+    let format = args.take_or("format", OutputFormat::Text)?;
+    let mut specs = args.take_all("ai")?;
+    if specs.is_empty() {
+        specs.push(String::from("chokudai"));
+    }
+    let ais = specs
+        .iter()
+        .map(|spec| build_ai(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match command.as_str() {
+        "play" => {
+            let seed = args.take_or("seed", 0)?;
+            let replan_interval = take_replan_interval(&mut args)?;
+            let board = args.take::<String>("board")?;
+            let record_path = args.take::<String>("record")?;
+            args.finish()?;
+
+            init_random_generator(seed);
+            let mut state = match board {
+                Some(path) => MazeState::from_board(&Board::load(path)?, rules)?,
+                None => MazeState::new(config, rules),
+            };
+            let mut record = GameRecord {
+                board: state.to_board(),
+                actions: Vec::new(),
+            };
+            if format == OutputFormat::Csv {
+                println!("turn,action,score");
+            } else {
+                println!("{}", state);
+            }
+            let mut planner = Planner::new(replan_interval);
+            let mut actions = Vec::new();
+            while !state.is_done() {
+                let action = planner.next_action(&ais[0], &state);
+                state.advance(action);
+                actions.push(action);
+                if state.mover == 0 {
+                    print_turn(&state, &actions, format);
+                    record.actions.push(std::mem::take(&mut actions));
+                }
+            }
+            if let Some(path) = record_path {
+                record.save(path)?;
+            }
+        }
+        "match" => {
+            let seeds = args.take_seeds(100)?;
+            let replan_interval = take_replan_interval(&mut args)?;
+            args.finish()?;
+
+            if format == OutputFormat::Csv {
+                println!("ai,seed,score");
+            }
+            for ai in &ais {
+                test_ai_score(config, rules, ai, replan_interval, seeds.clone(), format);
+            }
+        }
+        "gap" => {
+            let seeds = args.take_seeds(20)?;
+            args.finish()?;
+
+            if format == OutputFormat::Csv {
+                println!("seed,ai,score,optimal,gap");
+            }
+            report_optimality_gap(config, rules, &ais, seeds, format);
+        }
+        "replan" => {
+            let seeds = args.take_seeds(20)?;
+            let intervals: Vec<usize> = args
+                .take_list("intervals")?
+                .unwrap_or_else(|| vec![1, 10, 100]);
+            args.finish()?;
+            if intervals.contains(&0) {
+                return Err(
+                    CliError::Invalid(String::from("replan intervals must be positive")).into(),
+                );
+            }
+
+            if format == OutputFormat::Csv {
+                println!("ai,interval,score,ms");
+            }
+            for ai in &ais {
+                for &interval in &intervals {
+                    compare_replan_interval(config, rules, ai, interval, seeds.clone(), format);
+                }
+            }
+        }
+        "tune" => {
+            let seeds = args.take_seeds(20)?;
+            let replan_interval = take_replan_interval(&mut args)?;
+            let options = TuneOptions::take(&mut args)?;
+            args.finish()?;
+
+            if format == OutputFormat::Csv {
+                println!("ai,seed,score");
+            }
+            let base: AiSpec = specs[0].parse()?;
+            let tuned = tune::tune(&base, &options, |spec| {
+                let ai = build_ai(&spec.to_string())?;
+                Ok::<_, SpecError>(test_ai_score(
+                    config,
+                    rules,
+                    &ai,
+                    replan_interval,
+                    seeds.clone(),
+                    format,
+                ))
+            })?;
+            if format == OutputFormat::Text {
+                println!("{}", tuned);
+            }
+            if let Some(path) = &options.out {
+                tuned.save(path)?;
+            }
+        }
+        "sweep" => {
+            let seeds = args.take_seeds(20)?;
+            let searches: Vec<String> = args
+                .take_list("search")?
+                .unwrap_or_else(|| vec![String::from("beam"), String::from("chokudai")]);
+            let thresholds: Vec<u128> = args.take_list("ms")?.unwrap_or_else(|| vec![1, 2, 5, 10]);
+            let widths: Vec<usize> = args
+                .take_list("widths")?
+                .unwrap_or_else(|| vec![1, 2, 5, 10]);
+            let depths: Option<Vec<usize>> = args.take_list("depths")?;
+            args.finish()?;
+
+            let mut specs = Vec::new();
+            for search in &searches {
+                let depths: Vec<Option<usize>> = match (search.as_str(), &depths) {
+                    ("chokudai", Some(depths)) => depths.iter().copied().map(Some).collect(),
+                    ("beam" | "lean_beam" | "chokudai", _) => vec![None],
+                    _ => {
+                        return Err(CliError::Invalid(format!(
+                            "cannot sweep `{}`, expected beam, lean_beam or chokudai",
+                            search
+                        ))
+                        .into())
+                    }
+                };
+                for &ms in &thresholds {
+                    for &width in &widths {
+                        for &depth in &depths {
+                            specs.push(SweepPoint {
+                                search: search.clone(),
+                                width,
+                                depth,
+                                ms,
+                            });
+                        }
+                    }
+                }
+            }
+
+            if format == OutputFormat::Csv {
+                println!("search,width,depth,ms,games,mean,stddev,min,max,nodes");
+            }
+            for point in &specs {
+                sweep_point(config, rules, point, seeds.clone(), format)?;
+            }
+        }
+        "throughput" => {
+            let game_number = args.take_at_least("games", 100, 1)?;
+            let rounds = args.take_at_least("rounds", 1000, 1)?;
+            args.finish()?;
+
+            let states = get_sample_states(config, rules, game_number);
+            let actions: Vec<_> = states.iter().map(random_action).collect();
+            measure_throughput(&states, rounds, |i, state| state.advance(actions[i])).print(format);
+        }
+        "bench" => {
+            let game_number = args.take_at_least("games", 100, 1)?;
+            args.finish()?;
+
+            let states = get_sample_states(config, rules, game_number);
+            if format == OutputFormat::Csv {
+                println!("ai,states,ms");
+            }
+            for ai in &ais {
+                calc_execution_speed(ai, &states, format);
+            }
+        }
+        "replay" => {
+            let path = args
+                .take::<String>("record")?
+                .ok_or(CliError::MissingValue(String::from("record")))?;
+            let animation = Animation::take(&mut args)?;
+            let states_path = args.take::<String>("states")?;
+            args.finish()?;
+
+            let record = GameRecord::load(path)?;
+            let mut state = MazeState::from_board(&record.board, rules)?;
+            let mut frames = vec![state.frame(&[])];
+            match (animation, format) {
+                (Some(_), _) => {}
+                (None, OutputFormat::Csv) => println!("turn,action,score"),
+                (None, OutputFormat::Text) => println!("{}", state),
+            }
+            for actions in &record.actions {
+                if actions.len() != state.characters.len() {
+                    return Err(format!(
+                        "expected {} actions at turn {}, found {}",
+                        state.characters.len(),
+                        state.turn,
+                        actions.len()
+                    )
+                    .into());
+                }
+                for &action in actions {
+                    if state.is_done() || !state.legal_action().contains(&action) {
+                        return Err(
+                            format!("illegal action {} at turn {}", action, state.turn).into()
+                        );
+                    }
+                    state.advance(action);
+                }
+                frames.push(state.frame(actions));
+                if animation.is_none() {
+                    print_turn(&state, actions, format);
+                }
+            }
+            if let Some(path) = states_path {
+                animate::save_lines(path, &frames)?;
+            }
+            if let Some(animation) = animation {
+                animation.play(&frames)?;
+            }
+        }
+        "help" => println!("{}", USAGE),
+        "list" => print!("{}", ai_registry().describe()),
+        _ => return Err(CliError::UnknownCommand(command).into()),
+    }
+
+    Ok(())
 }
 
 fn main() {
-    test_ai_score(100);
+    let result = Args::parse(std::env::args().skip(1))
+        .map_err(|e| e.into())
+        .and_then(run);
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        if e.is::<CliError>() {
+            eprintln!("{}", USAGE);
+        }
+        std::process::exit(2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD: &str = "\
+3 4 4
+1
+1 1
+1 2 X 4
+5 0 7 8
+9 1 2 3
+";
+
+    #[test]
+    fn display_draws_the_board_after_a_move() {
+        let board: Board = BOARD.parse().unwrap();
+        let mut state = MazeState::from_board(&board, MazeRules::default()).unwrap();
+        state.advance(0);
+
+        assert_eq!(state.to_string(), "turn: 1\nscore: 1\n12#4\n5.78\n9@23\n");
+    }
+
+    #[test]
+    fn from_board_clears_start_cells_and_rejects_shared_starts() {
+        let board: Board = BOARD.replace("1\n1 1\n", "1\n0 0\n").parse().unwrap();
+        let state = MazeState::from_board(&board, MazeRules::default()).unwrap();
+        assert_eq!(state.points.get(0, 0), 0);
+
+        let board: Board = BOARD.replace("1\n1 1\n", "2\n1 1\n1 1\n").parse().unwrap();
+        assert!(matches!(
+            MazeState::from_board(&board, MazeRules::default()),
+            Err(BoardError::SharedStart { x: 1, y: 1 })
+        ));
+    }
 }
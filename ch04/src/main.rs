@@ -1,9 +1,21 @@
-use common::{get_random, init_random_generator};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::time::Instant;
 
-const H: usize = 5;
-const W: usize = 5;
-const END_TURN: usize = 5;
-const CHARACTER_N: usize = 3;
+use common::animate::{self, Animation, Cell, Frame};
+use common::board::{Board, BoardError, GameRecord};
+use common::cli::{csv_field, Args, CliError, OutputFormat};
+use common::spec::{AiSpec, Algorithm, ParamDef, Params, Registry, SpecError};
+use common::tune::{self, TuneOptions};
+use common::{get_random, init_random_generator, GameConfig};
+
+use local_search::{Budget, Cooling, Schedule};
+
+const DEFAULT_CONFIG: GameConfig = GameConfig::new(5, 5, 5, 3);
+
+struct Ai(String, Box<dyn Fn(&mut AutoMoveMazeState)>);
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 struct Coord {
@@ -19,6 +31,7 @@ impl Coord {
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 struct AutoMoveMazeState {
+    config: GameConfig,
     points: Vec<Vec<i32>>,
     turn: usize,
     characters: Vec<Coord>,
@@ -32,40 +45,81 @@ impl AutoMoveMazeState {
     #[allow(non_upper_case_globals)]
     const dy: [i32; 4] = [0, 0, 1, -1];
 
-    fn new() -> AutoMoveMazeState {
-        let mut points = vec![vec![0; H]; W];
-        for i in 0..W {
-            for j in 0..H {
-                points[i][j] = get_random(10) as i32;
+    fn new(config: GameConfig) -> AutoMoveMazeState {
+        let mut points = vec![vec![0; config.w]; config.h];
+        for row in points.iter_mut() {
+            for point in row.iter_mut() {
+                *point = get_random(10) as i32;
             }
         }
 
         AutoMoveMazeState {
+            config,
             points,
             turn: 0,
-            characters: vec![Coord::new(0, 0); CHARACTER_N],
+            characters: vec![Coord::new(0, 0); config.character_n],
             game_score: 0,
             evaluated_score: 0,
         }
     }
 
+    fn from_board(board: &Board) -> Result<AutoMoveMazeState, BoardError> {
+        board.expect_no_walls()?;
+        if board.characters.is_empty() {
+            board.expect_characters(1)?;
+        }
+        let points = board
+            .points
+            .iter()
+            .map(|row| row.iter().map(|&point| point as i32).collect())
+            .collect();
+
+        Ok(AutoMoveMazeState {
+            config: board.config(),
+            points,
+            turn: 0,
+            characters: board
+                .characters
+                .iter()
+                .map(|&(x, y)| Coord::new(x, y))
+                .collect(),
+            game_score: 0,
+            evaluated_score: 0,
+        })
+    }
+
+    fn to_board(&self) -> Board {
+        Board {
+            h: self.config.h,
+            w: self.config.w,
+            end_turn: self.config.end_turn - self.turn,
+            characters: self.characters.iter().map(|c| (c.x, c.y)).collect(),
+            points: self
+                .points
+                .iter()
+                .map(|row| row.iter().map(|&point| point as usize).collect())
+                .collect(),
+            walls: Vec::new(),
+        }
+    }
+
     fn set_character(&mut self, character_id: usize, x: i32, y: i32) {
         self.characters[character_id].x = x;
         self.characters[character_id].y = y;
     }
 
     const fn is_done(&self) -> bool {
-        self.turn == END_TURN
+        self.turn == self.config.end_turn
     }
 
     fn move_player(&mut self, character_id: usize) {
         let character = &mut self.characters[character_id];
-        let mut best_point = std::i32::MIN;
+        let mut best_point = i32::MIN;
         let mut best_action_index = 0;
         for act in 0..4 {
             let nx = character.x + Self::dx[act];
             let ny = character.y + Self::dy[act];
-            if nx >= 0 && nx < W as i32 && ny >= 0 && ny < H as i32 {
+            if self.config.is_inside(nx, ny) {
                 let point = self.points[nx as usize][ny as usize];
                 if point > best_point {
                     best_point = point;
@@ -78,7 +132,7 @@ impl AutoMoveMazeState {
     }
 
     fn advance(&mut self) {
-        for character_id in 0..CHARACTER_N {
+        for character_id in 0..self.characters.len() {
             self.move_player(character_id);
         }
 
@@ -90,7 +144,7 @@ impl AutoMoveMazeState {
         self.turn += 1;
     }
 
-    fn get_score(&mut self, is_print: bool) -> i64 {
+    fn get_score(&self, is_print: bool) -> i64 {
         let mut tmp_state = self.clone();
         for character in &tmp_state.characters {
             let point = &mut tmp_state.points[character.x as usize][character.y as usize];
@@ -100,7 +154,7 @@ impl AutoMoveMazeState {
         while !tmp_state.is_done() {
             tmp_state.advance();
             if is_print {
-                tmp_state.to_string();
+                println!("{}", tmp_state);
             }
         }
 
@@ -109,138 +163,1011 @@ impl AutoMoveMazeState {
 
     fn init(&mut self) {
         for character in &mut self.characters {
-            character.x = get_random(W) as i32;
-            character.y = get_random(H) as i32;
+            character.x = get_random(self.config.h) as i32;
+            character.y = get_random(self.config.w) as i32;
         }
     }
 
     fn transition(&mut self) {
-        let character = &mut self.characters[get_random(CHARACTER_N)];
-        character.x = get_random(W) as i32;
-        character.y = get_random(H) as i32;
-    }
-
-    #[allow(dead_code)]
-    fn to_string(&self) {
-        println!("turn: {}", self.turn);
-        println!("score: {}", self.game_score);
-        for i in 0..W {
-            for j in 0..H {
-                let mut is_written = false;
-                for character in &self.characters {
-                    if i == character.x as usize && j == character.y as usize {
-                        print!("@");
-                        is_written = true;
-                        break;
-                    }
-                }
-                if is_written {
-                    continue;
-                }
-                if self.points[i][j] > 0 {
-                    print!("{}", self.points[i][j]);
-                } else {
-                    print!(".");
+        let character = &mut self.characters[get_random(self.config.character_n)];
+        character.x = get_random(self.config.h) as i32;
+        character.y = get_random(self.config.w) as i32;
+    }
+
+    /// Moves a random character to one of the cells next to it.
+    fn shift(&mut self) {
+        let config = self.config;
+        let character = &mut self.characters[get_random(config.character_n)];
+        let next_coords: Vec<Coord> = (0..4)
+            .map(|action| {
+                Coord::new(
+                    character.x + Self::dx[action],
+                    character.y + Self::dy[action],
+                )
+            })
+            .filter(|coord| config.is_inside(coord.x, coord.y))
+            .collect();
+        if !next_coords.is_empty() {
+            *character = next_coords[get_random(next_coords.len())];
+        }
+    }
+
+    /// Picks two different random characters, or `None` with fewer than two.
+    fn random_pair(&self) -> Option<(usize, usize)> {
+        let n = self.config.character_n;
+        if n < 2 {
+            return None;
+        }
+        let i = get_random(n);
+        let j = (i + 1 + get_random(n - 1)) % n;
+
+        Some((i, j))
+    }
+
+    /// Exchanges two characters, which leaves the cells they stand on alone
+    /// and only changes the order they move in. That matters only when two
+    /// characters race for the same points, so the score rarely changes and
+    /// the move is off by default. A single character is moved by
+    /// `transition` instead.
+    fn swap(&mut self) {
+        match self.random_pair() {
+            Some((i, j)) => self.characters.swap(i, j),
+            None => self.transition(),
+        }
+    }
+
+    /// Moves two characters to random cells, or the only one there is.
+    fn relocate_pair(&mut self) {
+        match self.random_pair() {
+            Some(pair) => {
+                for i in [pair.0, pair.1] {
+                    self.characters[i].x = get_random(self.config.h) as i32;
+                    self.characters[i].y = get_random(self.config.w) as i32;
                 }
             }
-            println!();
+            None => self.transition(),
         }
-        println!();
+    }
+
+    /// The board as drawn by `to_string`, each character in its own color.
+    fn frame(&self) -> Frame {
+        let cells = (0..self.config.h)
+            .map(|i| {
+                (0..self.config.w)
+                    .map(|j| {
+                        let character = self
+                            .characters
+                            .iter()
+                            .position(|c| i == c.x as usize && j == c.y as usize);
+                        match character {
+                            Some(player) => Cell::Character { label: '@', player },
+                            None if self.points[i][j] > 0 => {
+                                Cell::Points(self.points[i][j] as usize)
+                            }
+                            None => Cell::Empty,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Frame {
+            turn: self.turn,
+            scores: vec![self.game_score],
+            notes: Vec::new(),
+            cells,
+        }
+    }
+
+    /// Every turn of the game `get_score` simulates, starting from the
+    /// placement.
+    fn frames(&self) -> Vec<Frame> {
+        let mut tmp_state = self.clone();
+        for character in &tmp_state.characters {
+            tmp_state.points[character.x as usize][character.y as usize] = 0;
+        }
+        let mut frames = vec![tmp_state.frame()];
+        while !tmp_state.is_done() {
+            tmp_state.advance();
+            frames.push(tmp_state.frame());
+        }
+
+        frames
+    }
+}
+
+impl fmt::Display for AutoMoveMazeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.frame())
     }
 }
 
 #[allow(dead_code)]
 fn random_action(state: &mut AutoMoveMazeState) {
-    for character_id in 0..CHARACTER_N {
-        let x = get_random(W) as i32;
-        let y = get_random(H) as i32;
+    for character_id in 0..state.config.character_n {
+        let x = get_random(state.config.h) as i32;
+        let y = get_random(state.config.w) as i32;
         state.set_character(character_id, x, y);
     }
 }
 
-#[allow(dead_code)]
-fn hill_climb(state: &mut AutoMoveMazeState, number: usize) {
-    state.init();
-    let mut best_score = state.get_score(false);
-    for _ in 0..number {
-        let mut next_state = state.clone();
-        next_state.transition();
-        let next_score = next_state.get_score(false);
-        if next_score > best_score {
-            best_score = next_score;
-            *state = next_state;
+/// A way of drawing a neighboring placement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Move {
+    /// [`AutoMoveMazeState::transition`]
+    Teleport,
+    /// [`AutoMoveMazeState::shift`]
+    Shift,
+    /// [`AutoMoveMazeState::swap`], which only reorders the characters.
+    Swap,
+    /// [`AutoMoveMazeState::relocate_pair`]
+    Pair,
+}
+
+impl Move {
+    const ALL: [Move; 4] = [Move::Teleport, Move::Shift, Move::Swap, Move::Pair];
+
+    fn name(self) -> &'static str {
+        match self {
+            Move::Teleport => "teleport",
+            Move::Shift => "shift",
+            Move::Swap => "swap",
+            Move::Pair => "pair",
+        }
+    }
+
+    fn apply(self, state: &mut AutoMoveMazeState) {
+        match self {
+            Move::Teleport => state.transition(),
+            Move::Shift => state.shift(),
+            Move::Swap => state.swap(),
+            Move::Pair => state.relocate_pair(),
         }
     }
 }
 
-#[allow(dead_code)]
-fn simulated_annealing(
-    state: &mut AutoMoveMazeState,
-    number: usize,
-    start_temp: f64,
-    end_temp: f64,
-) -> AutoMoveMazeState {
-    state.init();
-    let mut best_score = state.get_score(false);
-    let mut now_score = best_score;
-    let mut best_state = state.clone();
-    for i in 0..number {
+/// How often each [`Move`] is drawn, read from the spec parameters of the
+/// same names.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Neighborhood {
+    /// Weight of each move, in the order of [`Move::ALL`].
+    weights: [f64; 4],
+    /// Number of recent tries of a move whose acceptance rate scales its
+    /// weight, or 0 to keep the weights fixed.
+    window: usize,
+}
+
+impl Neighborhood {
+    /// Lowest acceptance rate a move is weighted by, so that a move that has
+    /// stopped being accepted is still tried now and then.
+    const MIN_RATE: f64 = 0.05;
+
+    fn from_params(params: &Params) -> Result<Neighborhood, SpecError> {
+        let mut weights = [0.0_f64; 4];
+        for (weight, m) in weights.iter_mut().zip(Move::ALL) {
+            *weight = params.get(m.name())?;
+            if !(weight.is_finite() && *weight >= 0.0) {
+                return Err(SpecError::InvalidValue {
+                    key: m.name().to_string(),
+                    value: weight.to_string(),
+                });
+            }
+        }
+        if weights.iter().all(|&weight| weight == 0.0) {
+            return Err(SpecError::InvalidValue {
+                key: String::from("teleport"),
+                value: String::from("0"),
+            });
+        }
+
+        Ok(Neighborhood {
+            weights,
+            window: params.get("adaptive")?,
+        })
+    }
+}
+
+/// Draws moves from a [`Neighborhood`], keeping the recent outcomes of each
+/// when it adapts.
+#[derive(Debug, Clone)]
+struct MoveSelector {
+    neighborhood: Neighborhood,
+    /// Whether each of the last tries of a move was accepted.
+    history: [VecDeque<bool>; 4],
+    last: usize,
+}
+
+impl MoveSelector {
+    fn new(neighborhood: Neighborhood) -> MoveSelector {
+        MoveSelector {
+            neighborhood,
+            history: Default::default(),
+            last: 0,
+        }
+    }
+
+    fn weight(&self, index: usize) -> f64 {
+        let weight = self.neighborhood.weights[index];
+        let history = &self.history[index];
+        if self.neighborhood.window == 0 || history.is_empty() {
+            return weight;
+        }
+        let rate =
+            history.iter().filter(|&&accepted| accepted).count() as f64 / history.len() as f64;
+
+        weight * rate.max(Neighborhood::MIN_RATE)
+    }
+
+    /// Draws a move. Draws no random number when only one move has a weight,
+    /// so that the default neighborhood searches as `transition` alone did.
+    fn choose(&mut self) -> Move {
+        let weights: Vec<f64> = (0..Move::ALL.len()).map(|i| self.weight(i)).collect();
+        let mut candidates = (0..Move::ALL.len()).filter(|&i| weights[i] > 0.0);
+        let first = candidates.next().unwrap_or(0);
+        let last = candidates.next_back();
+        self.last = match last {
+            None => first,
+            Some(last) => {
+                let total: f64 = weights.iter().sum();
+                let mut r = get_random(usize::MAX) as f64 / usize::MAX as f64 * total;
+                (0..Move::ALL.len())
+                    .find(|&i| {
+                        r -= weights[i];
+                        weights[i] > 0.0 && r < 0.0
+                    })
+                    .unwrap_or(last)
+            }
+        };
+
+        Move::ALL[self.last]
+    }
+
+    /// Records whether the last move drawn was accepted.
+    fn record(&mut self, accepted: bool) {
+        let window = self.neighborhood.window;
+        if window == 0 {
+            return;
+        }
+        let history = &mut self.history[self.last];
+        history.push_back(accepted);
+        if history.len() > window {
+            history.pop_front();
+        }
+    }
+}
+
+/// Placing the characters on the board of `base`, searched by [`local_search`].
+struct Placement<'a> {
+    base: &'a AutoMoveMazeState,
+    moves: MoveSelector,
+}
+
+impl<'a> Placement<'a> {
+    fn new(base: &'a AutoMoveMazeState, neighborhood: Neighborhood) -> Placement<'a> {
+        Placement {
+            base,
+            moves: MoveSelector::new(neighborhood),
+        }
+    }
+}
+
+impl local_search::Problem for Placement<'_> {
+    type Solution = AutoMoveMazeState;
+
+    fn initial(&mut self) -> AutoMoveMazeState {
+        let mut state = self.base.clone();
+        state.init();
+        state
+    }
+
+    fn neighbor(&mut self, state: &AutoMoveMazeState) -> AutoMoveMazeState {
         let mut next_state = state.clone();
-        next_state.transition();
-        let next_score = next_state.get_score(false);
-        let temp = start_temp + (end_temp - start_temp) * (i as f64 / number as f64);
-        let prob = f64::exp((next_score - now_score) as f64 / temp);
-        let is_force_next = prob > get_random(usize::MAX) as f64 / usize::MAX as f64;
-        if next_score > now_score || is_force_next {
-            now_score = next_score;
-            *state = next_state.clone();
+        self.moves.choose().apply(&mut next_state);
+        next_state
+    }
+
+    /// Replays the whole game. Every move changes a starting cell, so a
+    /// neighbor's game parts from the current one on the first turn, and
+    /// replaying from the turn the games part measured slower than this.
+    fn score(&self, state: &AutoMoveMazeState) -> i64 {
+        state.get_score(false)
+    }
+
+    fn accepted(&mut self, accepted: bool) {
+        self.moves.record(accepted);
+    }
+}
+
+mod local_search {
+    use common::{get_random, TimeKeeper};
+
+    /// An optimization problem whose solutions are improved by moving to
+    /// neighboring ones. Higher scores are better.
+    pub trait Problem {
+        type Solution: Clone;
+
+        /// A starting solution, usually drawn at random.
+        fn initial(&mut self) -> Self::Solution;
+
+        /// A solution near `solution`, usually drawn at random.
+        fn neighbor(&mut self, solution: &Self::Solution) -> Self::Solution;
+
+        fn score(&self, solution: &Self::Solution) -> i64;
+
+        /// Called after every neighbor with whether the search moved to it.
+        fn accepted(&mut self, _accepted: bool) {}
+    }
+
+    /// When a search stops.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Budget {
+        Iterations(usize),
+        /// Time threshold in milliseconds.
+        Time(u128),
+    }
+
+    impl Budget {
+        fn start(self) -> Clock {
+            let threshold = match self {
+                Budget::Iterations(_) => 0,
+                Budget::Time(threshold) => threshold,
+            };
+            Clock {
+                budget: self,
+                time_keeper: TimeKeeper::new(threshold),
+            }
+        }
+    }
+
+    struct Clock {
+        budget: Budget,
+        time_keeper: TimeKeeper,
+    }
+
+    impl Clock {
+        /// Fraction of the budget spent before iteration `i`, or `None` once
+        /// all of it is.
+        fn progress(&self, i: usize) -> Option<f64> {
+            match self.budget {
+                Budget::Iterations(number) => (i < number).then(|| i as f64 / number as f64),
+                Budget::Time(_) => {
+                    (!self.time_keeper.is_time_over()).then(|| self.time_keeper.progress())
+                }
+            }
         }
+    }
 
-        if next_score > best_score {
-            best_score = next_score;
-            best_state = next_state;
+    /// Moves to a neighbor whenever it scores higher.
+    pub fn hill_climb<P: Problem>(problem: &mut P, budget: Budget) -> P::Solution {
+        let clock = budget.start();
+        let mut solution = problem.initial();
+        let mut best_score = problem.score(&solution);
+        let mut i = 0;
+        while clock.progress(i).is_some() {
+            let next_solution = problem.neighbor(&solution);
+            let next_score = problem.score(&next_solution);
+            let is_accepted = next_score > best_score;
+            problem.accepted(is_accepted);
+            if is_accepted {
+                best_score = next_score;
+                solution = next_solution;
+            }
+            i += 1;
         }
+
+        solution
+    }
+
+    /// How the temperature falls from the start to the end temperature.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Schedule {
+        Linear,
+        /// Multiplied by the same factor at every step of progress.
+        Exponential,
+        /// Falls as the inverse of the logarithm of the progress, fast at
+        /// first and slowly afterwards.
+        Logarithmic,
+    }
+
+    /// The temperatures of [`simulated_annealing`].
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Cooling {
+        pub schedule: Schedule,
+        pub start_temp: f64,
+        pub end_temp: f64,
+        /// Times the temperature goes back to `start_temp`, splitting the
+        /// budget into `reheats + 1` equal coolings.
+        pub reheats: usize,
+        /// Number of neighbors of the initial solution sampled to choose the
+        /// start temperature, or 0 to keep `start_temp`.
+        pub calibrate: usize,
+    }
+
+    impl Cooling {
+        /// Probability with which a calibrated start temperature accepts a
+        /// neighbor losing the mean loss of the sampled ones.
+        const START_ACCEPTANCE: f64 = 0.5;
+
+        /// The temperature once `progress` of the budget is spent.
+        fn temperature(&self, start_temp: f64, progress: f64) -> f64 {
+            let progress = if self.reheats == 0 {
+                progress
+            } else {
+                (progress * (self.reheats + 1) as f64).fract()
+            };
+            let end_temp = self.end_temp;
+            match self.schedule {
+                Schedule::Linear => start_temp + (end_temp - start_temp) * progress,
+                Schedule::Exponential => start_temp * (end_temp / start_temp).powf(progress),
+                Schedule::Logarithmic => {
+                    let log = f64::ln(1.0 + (std::f64::consts::E - 1.0) * progress);
+                    start_temp / (1.0 + (start_temp / end_temp - 1.0) * log)
+                }
+            }
+        }
+
+        /// The start temperature at which the mean score lost by the worse
+        /// of `calibrate` neighbors of `solution` is accepted with
+        /// probability [`Cooling::START_ACCEPTANCE`], or `start_temp` if no
+        /// neighbor is worse.
+        fn start_temp<P: Problem>(&self, problem: &mut P, solution: &P::Solution) -> f64 {
+            let score = problem.score(solution);
+            let losses: Vec<i64> = (0..self.calibrate)
+                .map(|_| {
+                    let neighbor = problem.neighbor(solution);
+                    score - problem.score(&neighbor)
+                })
+                .filter(|&loss| loss > 0)
+                .collect();
+            if losses.is_empty() {
+                return self.start_temp;
+            }
+            let mean_loss = losses.iter().sum::<i64>() as f64 / losses.len() as f64;
+
+            -mean_loss / Self::START_ACCEPTANCE.ln()
+        }
+    }
+
+    /// Also moves to a worse neighbor with probability
+    /// `exp(score difference / temperature)`, the temperature following
+    /// `cooling` over the budget. Neighbors sampled for calibration count
+    /// against the budget. Returns the best solution seen.
+    pub fn simulated_annealing<P: Problem>(
+        problem: &mut P,
+        budget: Budget,
+        cooling: Cooling,
+    ) -> P::Solution {
+        let clock = budget.start();
+        let mut solution = problem.initial();
+        let start_temp = if cooling.calibrate > 0 {
+            cooling.start_temp(problem, &solution)
+        } else {
+            cooling.start_temp
+        };
+        let mut best_score = problem.score(&solution);
+        let mut now_score = best_score;
+        let mut best_solution = solution.clone();
+        let mut i = cooling.calibrate;
+        while let Some(progress) = clock.progress(i) {
+            let next_solution = problem.neighbor(&solution);
+            let next_score = problem.score(&next_solution);
+            let temp = cooling.temperature(start_temp, progress);
+            let prob = f64::exp((next_score - now_score) as f64 / temp);
+            let is_force_next = prob > get_random(usize::MAX) as f64 / usize::MAX as f64;
+            let is_accepted = next_score > now_score || is_force_next;
+            problem.accepted(is_accepted);
+            if is_accepted {
+                now_score = next_score;
+                solution = next_solution.clone();
+            }
+
+            if next_score > best_score {
+                best_score = next_score;
+                best_solution = next_solution;
+            }
+            i += 1;
+        }
+
+        best_solution
     }
-    best_state
 }
 
-#[allow(dead_code)]
-fn play_game() {
-    let mut state = AutoMoveMazeState::new();
-    // random_action(&mut state);
-    hill_climb(&mut state, 10000);
-    println!("Score of hill climb : {}", state.get_score(false));
-}
-
-fn test_ai_score(game_number: usize) {
-    let simulate_number = 10000;
-    let mut hill_climb_score_mean = 0;
-    let mut annealing_score_mean = 0;
-    for i in 0..game_number {
-        init_random_generator(i as u64);
-        let mut hill_climb_state = AutoMoveMazeState::new();
-        let mut annealing_state = hill_climb_state.clone();
-        hill_climb(&mut hill_climb_state, simulate_number);
-        hill_climb_score_mean += hill_climb_state.get_score(false);
-        annealing_state = simulated_annealing(
-            &mut annealing_state,
-            simulate_number,
-            f64::from(500),
-            f64::from(10),
-        );
-        annealing_score_mean += annealing_state.get_score(false);
-    }
-
-    println!(
-        "Score of hill climb: {}",
-        hill_climb_score_mean as f64 / game_number as f64
-    );
-    println!(
-        "Score of annealing : {}",
-        annealing_score_mean as f64 / game_number as f64
-    );
+/// Returns the mean score of each AI.
+fn test_ai_score(
+    config: GameConfig,
+    ais: &[Ai],
+    seeds: Range<u64>,
+    format: OutputFormat,
+) -> Vec<f64> {
+    let game_number = seeds.end - seeds.start;
+    let mut score_means = vec![0; ais.len()];
+    for seed in seeds {
+        init_random_generator(seed);
+        let base_state = AutoMoveMazeState::new(config);
+        for (ai, score_mean) in ais.iter().zip(score_means.iter_mut()) {
+            let mut state = base_state.clone();
+            ai.1(&mut state);
+            let score = state.get_score(false);
+            if format == OutputFormat::Csv {
+                println!("{},{},{}", csv_field(&ai.0), seed, score);
+            }
+            *score_mean += score;
+        }
+    }
+
+    let score_means: Vec<f64> = score_means
+        .into_iter()
+        .map(|score_mean| score_mean as f64 / game_number as f64)
+        .collect();
+    if format == OutputFormat::Text {
+        for (ai, score_mean) in ais.iter().zip(&score_means) {
+            println!("Score of {}: {}", ai.0, score_mean);
+        }
+    }
+
+    score_means
+}
+
+type Action = Box<dyn Fn(&mut AutoMoveMazeState)>;
+
+/// Appends the parameters shared by every local search to `params`.
+macro_rules! local_search_params {
+    ($($param:expr),* $(,)?) => {
+        &[
+            $($param,)*
+            ParamDef {
+                name: "teleport",
+                default: Some("1"),
+                help: "weight of moving a character to a random cell",
+            },
+            ParamDef {
+                name: "shift",
+                default: Some("0"),
+                help: "weight of moving a character to a cell next to it",
+            },
+            ParamDef {
+                name: "swap",
+                default: Some("0"),
+                help: "weight of exchanging the move order of two characters",
+            },
+            ParamDef {
+                name: "pair",
+                default: Some("0"),
+                help: "weight of moving two characters to random cells",
+            },
+            ParamDef {
+                name: "adaptive",
+                default: Some("0"),
+                help: "scale the weights by acceptance over this many recent tries, 0 for fixed",
+            },
+        ]
+    };
+}
+
+/// Hill climbing over `budget`.
+fn hill_climb(budget: Budget, params: &Params) -> Result<Action, SpecError> {
+    let neighborhood = Neighborhood::from_params(params)?;
+    Ok(Box::new(move |state| {
+        let mut placement = Placement::new(state, neighborhood);
+        *state = local_search::hill_climb(&mut placement, budget)
+    }))
+}
+
+/// Appends the parameters of simulated annealing to `params`.
+macro_rules! annealing_params {
+    ($($param:expr),* $(,)?) => {
+        local_search_params![
+            $($param,)*
+            ParamDef {
+                name: "start_temp",
+                default: Some("500"),
+                help: "initial temperature",
+            },
+            ParamDef {
+                name: "end_temp",
+                default: Some("10"),
+                help: "final temperature",
+            },
+            ParamDef {
+                name: "schedule",
+                default: Some("linear"),
+                help: "cooling: linear, exponential or logarithmic",
+            },
+            ParamDef {
+                name: "reheats",
+                default: Some("0"),
+                help: "times the temperature goes back up, each cooling as long",
+            },
+            ParamDef {
+                name: "calibrate",
+                default: Some("0"),
+                help: "neighbors sampled to choose start_temp, 0 to keep it",
+            },
+        ]
+    };
+}
+
+/// Reads the temperatures appended by [`annealing_params`].
+fn cooling(params: &Params) -> Result<Cooling, SpecError> {
+    let start_temp: f64 = params.get("start_temp")?;
+    let end_temp: f64 = params.get("end_temp")?;
+    let schedule: String = params.get("schedule")?;
+    let schedule = match schedule.as_str() {
+        "linear" => Schedule::Linear,
+        "exponential" => Schedule::Exponential,
+        "logarithmic" => Schedule::Logarithmic,
+        _ => {
+            return Err(SpecError::InvalidValue {
+                key: String::from("schedule"),
+                value: schedule,
+            })
+        }
+    };
+    // Only a linear schedule can reach a temperature of zero.
+    if schedule != Schedule::Linear {
+        for (key, temp) in [("start_temp", start_temp), ("end_temp", end_temp)] {
+            if temp.is_nan() || temp <= 0.0 {
+                return Err(SpecError::InvalidValue {
+                    key: String::from(key),
+                    value: temp.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(Cooling {
+        schedule,
+        start_temp,
+        end_temp,
+        reheats: params.get("reheats")?,
+        calibrate: params.get("calibrate")?,
+    })
+}
+
+/// Simulated annealing over `budget` with the temperatures of `params`.
+fn annealing(budget: Budget, params: &Params) -> Result<Action, SpecError> {
+    let cooling = cooling(params)?;
+    let neighborhood = Neighborhood::from_params(params)?;
+    Ok(Box::new(move |state| {
+        let mut placement = Placement::new(state, neighborhood);
+        *state = local_search::simulated_annealing(&mut placement, budget, cooling)
+    }))
+}
+
+fn ai_registry() -> Registry<Action> {
+    Registry::new(vec![
+        Algorithm {
+            name: "random",
+            help: "place every character at random",
+            params: &[],
+            build: |_| Ok(Box::new(random_action)),
+        },
+        Algorithm {
+            name: "hill_climb",
+            help: "hill climbing from a random placement",
+            params: local_search_params![ParamDef {
+                name: "number",
+                default: Some("10000"),
+                help: "number of iterations",
+            },],
+            build: |params| {
+                let budget = Budget::Iterations(params.get("number")?);
+                hill_climb(budget, params)
+            },
+        },
+        Algorithm {
+            name: "hill_climb_time",
+            help: "hill climbing with a time threshold",
+            params: local_search_params![ParamDef {
+                name: "ms",
+                default: Some("10"),
+                help: "time threshold in milliseconds",
+            },],
+            build: |params| {
+                let budget = Budget::Time(params.get("ms")?);
+                hill_climb(budget, params)
+            },
+        },
+        Algorithm {
+            name: "annealing",
+            help: "simulated annealing from a random placement",
+            params: annealing_params![ParamDef {
+                name: "number",
+                default: Some("10000"),
+                help: "number of iterations",
+            }],
+            build: |params| {
+                let budget = Budget::Iterations(params.get("number")?);
+                annealing(budget, params)
+            },
+        },
+        Algorithm {
+            name: "annealing_time",
+            help: "simulated annealing with a time threshold",
+            params: annealing_params![ParamDef {
+                name: "ms",
+                default: Some("10"),
+                help: "time threshold in milliseconds",
+            }],
+            build: |params| {
+                let budget = Budget::Time(params.get("ms")?);
+                annealing(budget, params)
+            },
+        },
+    ])
+}
+
+fn build_ai(spec: &str) -> Result<Ai, SpecError> {
+    let spec: AiSpec = spec.parse()?;
+    let action = ai_registry().build(&spec)?;
+
+    Ok(Ai(spec.to_string(), action))
+}
+
+const USAGE: &str = "\
+usage: ch04 [COMMAND] [OPTIONS]
+
+commands:
+    help      print this message
+    list      print the available AIs and their parameters
+    play      place the characters of one board and print every turn
+    match     print the mean score of each AI over the seeds (default)
+    bench     time each AI over the seeds
+    replay    print every turn of a recorded placement
+    tune      search the parameters of the first AI for the best mean score
+
+options:
+    --ai SPEC                 AI such as `annealing:number=1000,start_temp=300`;
+                              repeatable (default hill_climb and annealing)
+    --height N, --width N     board size (default 5x5)
+    --end-turn N              turn limit (default 5)
+    --characters N            number of characters (default 3)
+    --seed N                  play: seed of the board (default 0)
+    --seeds A..B, --games N   match, bench, tune: seeds to play (default 0..1000, bench 0..10,
+                              tune 0..100)
+    --board FILE              play: start from a board file
+    --record FILE             play: save the placement; replay: placement to print
+    --states FILE             replay: write every turn to FILE as one line
+    --animate                 replay: redraw the board in place every turn, controlled from stdin
+    --delay MS                replay: time each turn stays on screen when animating (default 300)
+    --no-color                replay: animate without colors, as does setting NO_COLOR
+    --param NAME=LOW..HIGH    tune: range of a parameter, integer if both bounds are; repeatable
+    --samples N               tune: random candidates before SPSA refines the best (default 20)
+    --iterations N            tune: SPSA steps of two candidates each (default 20)
+    --tune-seed N             tune: seed of the candidates, apart from the game seeds (default 0)
+    --out FILE                tune: write the best spec to FILE
+    --format text|csv         output format (default text)";
+
+fn run(mut args: Args) -> Result<(), Box<dyn Error>> {
+    let command = args.command.take().unwrap_or_else(|| String::from("match"));
+    let config = args.take_config(DEFAULT_CONFIG)?;
+    // Characters move every turn, so they need a cell next to them.
+    if config.h * config.w < 2 {
+        return Err(CliError::Invalid(String::from("the board needs at least two cells")).into());
+    }
+    let format = args.take_or("format", OutputFormat::Text)?;
+    let mut specs = args.take_all("ai")?;
+    if specs.is_empty() {
+        specs = vec![String::from("hill_climb"), String::from("annealing")];
+    }
+    let ais = specs
+        .iter()
+        .map(|spec| build_ai(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match command.as_str() {
+        "help" => println!("{}", USAGE),
+        "list" => print!("{}", ai_registry().describe()),
+        "play" => {
+            let seed = args.take_or("seed", 0)?;
+            let board = args.take::<String>("board")?;
+            let record_path = args.take::<String>("record")?;
+            args.finish()?;
+
+            init_random_generator(seed);
+            let mut state = match board {
+                Some(path) => AutoMoveMazeState::from_board(&Board::load(path)?)?,
+                None => AutoMoveMazeState::new(config),
+            };
+            ais[0].1(&mut state);
+            let score = state.get_score(format == OutputFormat::Text);
+            match format {
+                OutputFormat::Text => println!("Score of {}: {}", ais[0].0, score),
+                OutputFormat::Csv => {
+                    println!("ai,seed,score\n{},{},{}", csv_field(&ais[0].0), seed, score)
+                }
+            }
+            if let Some(path) = record_path {
+                let record = GameRecord {
+                    board: state.to_board(),
+                    actions: Vec::new(),
+                };
+                record.save(path)?;
+            }
+        }
+        "match" => {
+            let seeds = args.take_seeds(1000)?;
+            args.finish()?;
+
+            if format == OutputFormat::Csv {
+                println!("ai,seed,score");
+            }
+            test_ai_score(config, &ais, seeds, format);
+        }
+        "tune" => {
+            let seeds = args.take_seeds(100)?;
+            let options = TuneOptions::take(&mut args)?;
+            args.finish()?;
+
+            if format == OutputFormat::Csv {
+                println!("ai,seed,score");
+            }
+            let base: AiSpec = specs[0].parse()?;
+            let tuned = tune::tune(&base, &options, |spec| {
+                let ai = build_ai(&spec.to_string())?;
+                Ok::<_, SpecError>(test_ai_score(config, &[ai], seeds.clone(), format)[0])
+            })?;
+            if format == OutputFormat::Text {
+                println!("{}", tuned);
+            }
+            if let Some(path) = &options.out {
+                tuned.save(path)?;
+            }
+        }
+        "bench" => {
+            let seeds = args.take_seeds(10)?;
+            args.finish()?;
+
+            if format == OutputFormat::Csv {
+                println!("ai,games,ms");
+            }
+            for ai in &ais {
+                let start_time = Instant::now();
+                for seed in seeds.clone() {
+                    init_random_generator(seed);
+                    let mut state = AutoMoveMazeState::new(config);
+                    ai.1(&mut state);
+                }
+                let diff = Instant::now() - start_time;
+                let game_number = seeds.end - seeds.start;
+                match format {
+                    OutputFormat::Text => println!(
+                        "{} take {} ms to process {} games",
+                        ai.0,
+                        diff.as_millis(),
+                        game_number
+                    ),
+                    OutputFormat::Csv => {
+                        println!("{},{},{}", csv_field(&ai.0), game_number, diff.as_millis())
+                    }
+                }
+            }
+        }
+        "replay" => {
+            let path = args
+                .take::<String>("record")?
+                .ok_or(CliError::MissingValue(String::from("record")))?;
+            let animation = Animation::take(&mut args)?;
+            let states_path = args.take::<String>("states")?;
+            args.finish()?;
+
+            let record = GameRecord::load(path)?;
+            let state = AutoMoveMazeState::from_board(&record.board)?;
+            if let Some(path) = states_path {
+                animate::save_lines(path, &state.frames())?;
+            }
+            if let Some(animation) = animation {
+                animation.play(&state.frames())?;
+                return Ok(());
+            }
+            let score = state.get_score(format == OutputFormat::Text);
+            match format {
+                OutputFormat::Text => println!("Score: {}", score),
+                OutputFormat::Csv => println!("score\n{}", score),
+            }
+        }
+        _ => return Err(CliError::UnknownCommand(command).into()),
+    }
+
+    Ok(())
 }
 
 fn main() {
-    test_ai_score(1000);
+    let result = Args::parse(std::env::args().skip(1))
+        .map_err(|e| e.into())
+        .and_then(run);
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        if e.is::<CliError>() {
+            eprintln!("{}", USAGE);
+        }
+        std::process::exit(2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD: &str = "\
+3 4 3
+2
+0 0
+2 3
+1 2 3 4
+5 0 7 8
+9 1 2 3
+";
+
+    #[test]
+    fn display_draws_the_board_after_a_turn() {
+        let board: Board = BOARD.parse().unwrap();
+        let mut state = AutoMoveMazeState::from_board(&board).unwrap();
+        state.advance();
+
+        assert_eq!(state.to_string(), "turn: 1\nscore: 13\n1234\n@.7@\n9123\n");
+    }
+
+    /// Indices of the characters placed differently in `before` and `after`.
+    fn moved(before: &AutoMoveMazeState, after: &AutoMoveMazeState) -> Vec<usize> {
+        (0..before.characters.len())
+            .filter(|&i| before.characters[i] != after.characters[i])
+            .collect()
+    }
+
+    fn is_on_board(state: &AutoMoveMazeState) -> bool {
+        state
+            .characters
+            .iter()
+            .all(|c| state.config.is_inside(c.x, c.y))
+    }
+
+    #[test]
+    fn every_move_keeps_the_characters_on_the_board() {
+        let board: Board = BOARD.parse().unwrap();
+        let state = AutoMoveMazeState::from_board(&board).unwrap();
+        for m in Move::ALL {
+            for _ in 0..100 {
+                let mut next_state = state.clone();
+                m.apply(&mut next_state);
+                let moved = moved(&state, &next_state);
+
+                assert!(is_on_board(&next_state), "{}", m.name());
+                match m {
+                    Move::Teleport => assert!(moved.len() <= 1),
+                    Move::Shift => {
+                        assert_eq!(moved.len(), 1);
+                        let (before, after) =
+                            (state.characters[moved[0]], next_state.characters[moved[0]]);
+                        assert_eq!((before.x - after.x).abs() + (before.y - after.y).abs(), 1);
+                    }
+                    Move::Swap => {
+                        assert_eq!(moved, vec![0, 1]);
+                        assert_eq!(next_state.characters[0], state.characters[1]);
+                        assert_eq!(next_state.characters[1], state.characters[0]);
+                    }
+                    Move::Pair => assert!(moved.len() <= 2),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn adaptive_selection_favors_accepted_moves() {
+        let mut moves = MoveSelector::new(Neighborhood {
+            weights: [1.0, 1.0, 0.0, 0.0],
+            window: 10,
+        });
+        for (index, accepted) in [(0, true), (1, false)] {
+            moves.last = index;
+            for _ in 0..20 {
+                moves.record(accepted);
+            }
+        }
+
+        assert_eq!(moves.history[0].len(), 10);
+        assert_eq!(moves.weight(0), 1.0);
+        assert_eq!(moves.weight(1), Neighborhood::MIN_RATE);
+        assert_eq!(moves.weight(2), 0.0);
+    }
 }
@@ -1,15 +1,30 @@
 use std::cmp::Ordering;
 
-use common::{get_random, init_random_generator, TimeKeeper};
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use common::animate::{self, Animation, Cell, Frame};
+use common::board::{Board, BoardError, GameRecord};
+use common::cli::{csv_field, read_direction, Args, CliError, OutputFormat, DIRECTION_NAMES};
+use common::grid::{Grid, PointGrid};
+use common::spec::{AiSpec, Algorithm, ParamDef, Params, Registry, SpecError};
+use common::tune::{self, TuneOptions};
+use common::{get_random, init_random_generator, measure_throughput, GameConfig, TimeKeeper};
 
 use iterative_deepening::iterative_deepening_action;
 use thunder::thunder_search_action_with_time_threshold;
 
-const H: usize = 5;
-const W: usize = 5;
-const END_TURN: usize = 10;
+const DEFAULT_CONFIG: GameConfig = GameConfig::new(5, 5, 10, 2);
 
-struct Ai(String, Box<dyn Fn(&AlternateMazeState) -> usize>);
+struct Ai(String, Action);
+
+impl Ai {
+    /// The action of an AI that never quits, as every AI but `human`.
+    fn action(&self, state: &AlternateMazeState) -> usize {
+        self.1(state).expect("only a human player quits")
+    }
+}
 
 #[derive(Debug)]
 enum WinningStatus {
@@ -38,7 +53,8 @@ impl Character {
 
 #[derive(Debug, Clone)]
 pub struct AlternateMazeState {
-    points: Vec<Vec<usize>>,
+    config: GameConfig,
+    points: PointGrid,
     turn: usize,
     characters: Vec<Character>,
 }
@@ -49,33 +65,66 @@ impl AlternateMazeState {
     #[allow(non_upper_case_globals)]
     const dy: [i32; 4] = [0, 0, 1, -1];
 
-    fn new() -> AlternateMazeState {
-        let mut points = vec![vec![0; W]; H];
-        for i in 0..H {
-            for j in 0..W {
-                if i == H / 2 && (j == W / 2 - 1 || j == W / 2 + 1) {
+    fn new(config: GameConfig) -> AlternateMazeState {
+        let (h, w) = (config.h, config.w);
+        let mut points = Grid::new(h, w, 0);
+        for i in 0..h {
+            for j in 0..w {
+                if i == h / 2 && (j == w / 2 - 1 || j == w / 2 + 1) {
                     continue;
                 }
-                points[i][j] = get_random(10);
+                points[(i, j)] = get_random(10);
             }
         }
 
         AlternateMazeState {
-            points,
+            config,
+            points: PointGrid::new(points),
             turn: 0,
             characters: vec![
-                Character::new(H as i32 / 2, W as i32 / 2 - 1),
-                Character::new(H as i32 / 2, W as i32 / 2 + 1),
+                Character::new(h as i32 / 2, w as i32 / 2 - 1),
+                Character::new(h as i32 / 2, w as i32 / 2 + 1),
             ],
         }
     }
 
+    fn from_board(board: &Board) -> Result<AlternateMazeState, BoardError> {
+        board.expect_characters(2)?;
+        board.expect_no_walls()?;
+
+        Ok(AlternateMazeState {
+            config: board.config(),
+            points: PointGrid::from_rows(&board.points),
+            turn: 0,
+            characters: board
+                .characters
+                .iter()
+                .map(|&(x, y)| Character::new(x, y))
+                .collect(),
+        })
+    }
+
+    fn to_board(&self) -> Board {
+        Board {
+            h: self.config.h,
+            w: self.config.w,
+            end_turn: self.remaining_turns(),
+            characters: self.characters.iter().map(|c| (c.x, c.y)).collect(),
+            points: self.points.to_rows(),
+            walls: Vec::new(),
+        }
+    }
+
+    const fn remaining_turns(&self) -> usize {
+        self.config.end_turn - self.turn
+    }
+
     const fn is_first_player(&self) -> bool {
-        self.turn % 2 == 0
+        self.turn.is_multiple_of(2)
     }
 
     const fn is_done(&self) -> bool {
-        self.turn == END_TURN
+        self.turn == self.config.end_turn
     }
 
     fn advance(&mut self, action: usize) {
@@ -83,11 +132,10 @@ impl AlternateMazeState {
         character.x += Self::dx[action];
         character.y += Self::dy[action];
 
-        let point = &mut self.points[character.x as usize][character.y as usize];
-        if *point > 0 {
-            character.game_score += *point as i32;
-            *point = 0;
-        }
+        let point = self
+            .points
+            .consume(character.x as usize, character.y as usize);
+        character.game_score += point as i32;
         self.turn += 1;
         self.characters.swap(0, 1);
     }
@@ -98,7 +146,7 @@ impl AlternateMazeState {
         for i in 0..4 {
             let nx = character.x + Self::dx[i];
             let ny = character.y + Self::dy[i];
-            if nx >= 0 && nx < H as i32 && ny >= 0 && ny < W as i32 {
+            if self.config.is_inside(nx, ny) {
                 actions.push(i);
             }
         }
@@ -153,8 +201,62 @@ impl AlternateMazeState {
         }
     }
 
-    fn to_string(&self) {
-        println!("turn: {}", self.turn);
+    /// The board as drawn by `to_string`, A being the first player whoever
+    /// is to move, with the last action named under the scores.
+    fn frame(&self, last_action: Option<usize>) -> Frame {
+        // characters[0] is the player to move, the first one on even turns.
+        let first = self.turn % 2;
+        let player_of = |index: usize| (index + first) % 2;
+        let mut scores = vec![0; self.characters.len()];
+        for (index, character) in self.characters.iter().enumerate() {
+            scores[player_of(index)] = character.game_score as i64;
+        }
+        let notes = match last_action {
+            Some(action) => vec![format!(
+                "{} moved {}",
+                if first == 1 { 'A' } else { 'B' },
+                DIRECTION_NAMES[action]
+            )],
+            None => Vec::new(),
+        };
+        let cells = (0..self.config.h)
+            .map(|h| {
+                (0..self.config.w)
+                    .map(|w| {
+                        let character = self
+                            .characters
+                            .iter()
+                            .rposition(|c| c.x as usize == h && c.y as usize == w);
+                        match character {
+                            Some(index) => {
+                                let player = player_of(index);
+                                Cell::Character {
+                                    label: (b'A' + player as u8) as char,
+                                    player,
+                                }
+                            }
+                            None if self.points.get(h, w) > 0 => {
+                                Cell::Points(self.points.get(h, w))
+                            }
+                            None => Cell::Empty,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Frame {
+            turn: self.turn,
+            scores,
+            notes,
+            cells,
+        }
+    }
+}
+
+impl fmt::Display for AlternateMazeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "turn: {}", self.turn)?;
         // Print the score and the position
         for player_id in 0..self.characters.len() {
             let mut actual_player_id = player_id;
@@ -163,40 +265,15 @@ impl AlternateMazeState {
                 actual_player_id = (player_id + 1) % 2;
             }
             let character = &self.characters[actual_player_id];
-            println!("score ({}): {}", player_id, character.game_score);
-            println!("x: {}, y: {}", character.x, character.y);
-        }
-
-        for h in 0..H {
-            for w in 0..W {
-                let mut is_written = false;
-                for (player_id, character) in self.characters.iter().enumerate() {
-                    let mut actual_player_id = player_id;
-                    // If the turn is odd, print the view from the opponent side.
-                    if self.turn % 2 == 1 {
-                        actual_player_id = (player_id + 1) % 2;
-                    }
+            writeln!(f, "score ({}): {}", player_id, character.game_score)?;
+            writeln!(f, "x: {}, y: {}", character.x, character.y)?;
+        }
 
-                    if character.x as usize == h && character.y as usize == w {
-                        if actual_player_id == 0 {
-                            print!("A");
-                        } else {
-                            print!("B");
-                        }
-                        is_written = true;
-                    }
-                }
-                if !is_written {
-                    if self.points[h][w] > 0 {
-                        print!("{}", self.points[h][w]);
-                    } else {
-                        print!(".");
-                    }
-                }
-            }
-            println!();
+        for row in self.frame(None).rows() {
+            writeln!(f, "{}", row)?;
         }
-        println!();
+
+        Ok(())
     }
 }
 
@@ -207,14 +284,13 @@ fn random_action(state: &AlternateMazeState) -> usize {
     legal_actions[get_random(legal_actions.len())]
 }
 
-#[allow(dead_code)]
-fn get_sample_states(game_number: usize) -> Vec<AlternateMazeState> {
+fn get_sample_states(config: GameConfig, game_number: usize) -> Vec<AlternateMazeState> {
     init_random_generator(0);
 
     let mut states = Vec::new();
     for _ in 0..game_number {
-        let mut state = AlternateMazeState::new();
-        let turn = get_random(usize::MAX) % END_TURN;
+        let mut state = AlternateMazeState::new(config);
+        let turn = get_random(usize::MAX) % config.end_turn;
         for _ in 0..turn {
             state.advance(random_action(&state));
         }
@@ -224,103 +300,142 @@ fn get_sample_states(game_number: usize) -> Vec<AlternateMazeState> {
     states
 }
 
-#[allow(dead_code)]
-fn calc_execution_speed(ai: &Ai, states: &Vec<AlternateMazeState>) {
+fn calc_execution_speed(ai: &Ai, states: &[AlternateMazeState], format: OutputFormat) {
     use std::time;
 
     let start_time = time::Instant::now();
     for state in states {
-        ai.1(state);
+        ai.action(state);
     }
     let diff = time::Instant::now() - start_time;
-    println!(
-        "{} take {} ms to process {} nodes",
-        ai.0,
-        diff.as_millis(),
-        states.len()
-    );
+    match format {
+        OutputFormat::Text => println!(
+            "{} take {} ms to process {} nodes",
+            ai.0,
+            diff.as_millis(),
+            states.len()
+        ),
+        OutputFormat::Csv => println!("{},{},{}", csv_field(&ai.0), states.len(), diff.as_millis()),
+    }
 }
 
-#[allow(dead_code)]
-fn test_first_player_win_rate(ais: Vec<Ai>, game_number: usize) {
-    let mut first_player_win_rate = 0.0;
-    for i in 0..game_number {
-        init_random_generator(i as u64);
-
-        let base_state = AlternateMazeState::new();
-        for j in 0..2 {
-            let mut state = base_state.clone();
-            let first_ai = &ais[j];
-            let second_ai = &ais[(j + 1) % 2];
-            loop {
-                state.advance(first_ai.1(&state));
-                if state.is_done() {
-                    break;
-                }
-                state.advance(second_ai.1(&state));
-                if state.is_done() {
-                    break;
-                }
+/// Plays `ais[0]` against `ais[1]` on the board of `seed`, moving first and
+/// then second, and returns each final state with the points of `ais[0]`.
+fn play_both_sides(config: GameConfig, ais: &[Ai], seed: u64) -> [(AlternateMazeState, f32); 2] {
+    init_random_generator(seed);
+
+    let base_state = AlternateMazeState::new(config);
+    [0, 1].map(|j| {
+        let mut state = base_state.clone();
+        let first_ai = &ais[j];
+        let second_ai = &ais[(j + 1) % 2];
+        loop {
+            state.advance(first_ai.action(&state));
+            if state.is_done() {
+                break;
             }
-            let mut win_rate_point = state.get_first_player_score_for_winning_rate();
-            if j == 1 {
-                win_rate_point = 1.0 - win_rate_point;
+            state.advance(second_ai.action(&state));
+            if state.is_done() {
+                break;
             }
-            if win_rate_point >= 0.0 {
-                state.to_string();
+        }
+        let mut win_rate_point = state.get_first_player_score_for_winning_rate();
+        if j == 1 {
+            win_rate_point = 1.0 - win_rate_point;
+        }
+        (state, win_rate_point)
+    })
+}
+
+fn test_first_player_win_rate(
+    config: GameConfig,
+    ais: &[Ai],
+    seeds: Range<u64>,
+    format: OutputFormat,
+) {
+    let game_number = seeds.end - seeds.start;
+    let mut first_player_win_rate = 0.0;
+    for (i, seed) in seeds.enumerate() {
+        for (j, (state, win_rate_point)) in play_both_sides(config, ais, seed).iter().enumerate() {
+            match format {
+                OutputFormat::Text => println!("{}", state),
+                OutputFormat::Csv => println!("{},{},{}", seed, j, win_rate_point),
             }
             first_player_win_rate += win_rate_point;
         }
-        println!("i {} w {}", i, first_player_win_rate / ((i + 1) * 2) as f32);
+        if format == OutputFormat::Text {
+            println!("i {} w {}", i, first_player_win_rate / ((i + 1) * 2) as f32);
+        }
     }
     first_player_win_rate /= (game_number * 2) as f32;
 
-    println!(
-        "Winning rate of {} to {} : {}",
-        &ais[0].0, &ais[1].0, first_player_win_rate
-    );
+    if format == OutputFormat::Text {
+        println!(
+            "Winning rate of {} to {} : {}",
+            &ais[0].0, &ais[1].0, first_player_win_rate
+        );
+    }
 }
 
-#[allow(dead_code)]
-fn play_game() {
-    init_random_generator(0);
+/// Plays the actions of `record` from its board. Returns every state with
+/// the action that led to it, starting from the board itself.
+fn replay(record: &GameRecord) -> Result<Vec<(AlternateMazeState, Option<usize>)>, String> {
+    let mut state = AlternateMazeState::from_board(&record.board).map_err(|e| e.to_string())?;
+    let mut turns = vec![(state.clone(), None)];
+    for actions in &record.actions {
+        let act = *actions.first().ok_or("empty action line")?;
+        if state.is_done() || !state.legal_actions().contains(&act) {
+            return Err(format!("illegal action {} at turn {}", act, state.turn));
+        }
+        state.advance(act);
+        turns.push((state.clone(), Some(act)));
+    }
 
-    let mut state = AlternateMazeState::new();
-    state.to_string();
+    Ok(turns)
+}
+
+fn print_winner(state: &AlternateMazeState) {
+    // The status is seen from the player who moves next.
+    let is_first_player_next = state.is_first_player();
+    match state.get_winning_status() {
+        WinningStatus::Win if is_first_player_next => println!("Winner: Player 1"),
+        WinningStatus::Win => println!("Winner: Player 2"),
+        WinningStatus::Lose if is_first_player_next => println!("Winner: Player 2"),
+        WinningStatus::Lose => println!("Winner: Player 1"),
+        _ => println!("Draw"),
+    }
+}
+
+fn play_game(mut state: AlternateMazeState, ais: &[Ai], format: OutputFormat) -> Vec<usize> {
+    let mut actions = Vec::new();
+    match format {
+        OutputFormat::Text => println!("{}", state),
+        OutputFormat::Csv => println!("turn,player,action"),
+    }
     while !state.is_done() {
-        // Player 1
-        {
-            println!("Player 1 -----------------------");
-            let act = minimax::minimax_action(&state, END_TURN) as usize;
-            println!("action: {}", act);
-            state.advance(act);
-            state.to_string();
-            if state.is_done() {
-                match state.get_winning_status() {
-                    WinningStatus::Win => println!("Winner: Player 2"),
-                    WinningStatus::Lose => println!("Winner: Player 1"),
-                    _ => println!("Draw"),
-                }
-                break;
+        let player_id = state.turn % 2;
+        let Some(act) = ais[player_id].1(&state) else {
+            if format == OutputFormat::Text {
+                println!("Player {} quits", player_id + 1);
             }
-        }
-        // Player 2
-        {
-            println!("Player 2 -----------------------");
-            let act = random_action(&state);
+            return actions;
+        };
+        actions.push(act);
+        if format == OutputFormat::Text {
+            println!("Player {} -----------------------", player_id + 1);
             println!("action: {}", act);
-            state.advance(act);
-            state.to_string();
-            if state.is_done() {
-                match state.get_winning_status() {
-                    WinningStatus::Win => println!("Winner: Player 1"),
-                    WinningStatus::Lose => println!("Winner: Player 2"),
-                    _ => println!("Draw"),
-                }
-                break;
-            }
         }
+        state.advance(act);
+        match format {
+            OutputFormat::Text => println!("{}", state),
+            OutputFormat::Csv => println!("{},{},{}", state.turn, player_id + 1, act),
+        }
+    }
+    if format == OutputFormat::Text {
+        print_winner(&state);
     }
+
+    actions
 }
 
 #[allow(dead_code)]
@@ -490,11 +605,14 @@ mod iterative_deepening {
 
 #[allow(dead_code)]
 mod montecarlo {
-    const C: f32 = 1.0;
-    const EXPAND_THRESHOLD: usize = 10;
-
     use super::{random_action, AlternateMazeState, TimeKeeper, WinningStatus};
 
+    #[derive(Debug, Clone, Copy)]
+    pub struct UctParams {
+        pub c: f32,
+        pub expand_threshold: usize,
+    }
+
     #[derive(Debug, Clone)]
     struct Node {
         state: AlternateMazeState,
@@ -513,7 +631,7 @@ mod montecarlo {
             }
         }
 
-        fn next_child_node(&mut self) -> usize {
+        fn next_child_node(&mut self, c: f32) -> usize {
             for (i, child_node) in self.child_nodes.iter().enumerate() {
                 if child_node.n == 0 {
                     return i;
@@ -528,7 +646,7 @@ mod montecarlo {
             for i in 0..self.child_nodes.len() {
                 let child_node = &self.child_nodes[i];
                 let ucb1_value = 1.0 - child_node.w / child_node.n as f32
-                    + C * (2.0 * t.ln() / child_node.n as f32).sqrt();
+                    + c * (2.0 * t.ln() / child_node.n as f32).sqrt();
                 if ucb1_value > best_value {
                     best_value = ucb1_value;
                     best_action_index = i as i32;
@@ -547,7 +665,7 @@ mod montecarlo {
             }
         }
 
-        fn evaluate(&mut self) -> f32 {
+        fn evaluate(&mut self, params: UctParams) -> f32 {
             if self.state.is_done() {
                 let mut value = 0.5;
                 match self.state.get_winning_status() {
@@ -565,18 +683,18 @@ mod montecarlo {
                 let value = playout(&mut state_copy);
                 self.w += value;
                 self.n += 1;
-                if self.n == EXPAND_THRESHOLD {
+                if self.n == params.expand_threshold {
                     self.expand();
                 }
                 value
             } else {
-                let next_child_node_index = self.next_child_node();
+                let next_child_node_index = self.next_child_node(params.c);
                 let value = 1.0
                     - self
                         .child_nodes
                         .get_mut(next_child_node_index)
                         .unwrap()
-                        .evaluate();
+                        .evaluate(params);
                 self.w += value;
                 self.n += 1;
 
@@ -595,12 +713,17 @@ mod montecarlo {
         }
     }
 
-    pub fn mcts_action(state: &AlternateMazeState, playout_number: usize, is_print: bool) -> usize {
+    pub fn mcts_action(
+        state: &AlternateMazeState,
+        playout_number: usize,
+        params: UctParams,
+        is_print: bool,
+    ) -> usize {
         use std::sync::atomic::AtomicBool;
         let mut root_node = Node::new(state.clone());
         root_node.expand();
         for _ in 0..playout_number {
-            root_node.evaluate();
+            root_node.evaluate(params);
         }
         let legal_actions = state.legal_actions();
         let mut best_action_searched_number = -1;
@@ -613,20 +736,21 @@ mod montecarlo {
             }
         }
         {
-            static mut CALLED_COUNT: AtomicBool = AtomicBool::new(false);
-            unsafe {
-                let called_count = CALLED_COUNT.get_mut();
-                if is_print && !*called_count {
-                    root_node.print_tree(0);
-                }
-                *called_count = true;
+            static CALLED_COUNT: AtomicBool = AtomicBool::new(false);
+            let called_count = CALLED_COUNT.swap(true, std::sync::atomic::Ordering::Relaxed);
+            if is_print && !called_count {
+                root_node.print_tree(0);
             }
         }
 
         legal_actions[best_action_index as usize]
     }
 
-    pub fn mcts_action_with_time_threshold(state: &AlternateMazeState, threshold: u128) -> usize {
+    pub fn mcts_action_with_time_threshold(
+        state: &AlternateMazeState,
+        threshold: u128,
+        params: UctParams,
+    ) -> usize {
         let mut root_node = Node::new(state.clone());
         root_node.expand();
         let time_keeper = TimeKeeper::new(threshold);
@@ -634,7 +758,7 @@ mod montecarlo {
             if time_keeper.is_time_over() {
                 break;
             }
-            root_node.evaluate();
+            root_node.evaluate(params);
         }
         let legal_actions = state.legal_actions();
         let mut best_action_searched_number = -1;
@@ -821,16 +945,470 @@ mod thunder {
     }
 }
 
+/// Picks the action of the player to move, or `None` when a human quits.
+type Action = Box<dyn Fn(&AlternateMazeState) -> Option<usize>>;
+
+fn search(action: impl Fn(&AlternateMazeState) -> usize + 'static) -> Action {
+    Box::new(move |state| Some(action(state)))
+}
+
+fn uct_params(params: &Params) -> Result<montecarlo::UctParams, SpecError> {
+    Ok(montecarlo::UctParams {
+        c: params.get("c")?,
+        expand_threshold: params.get("expand")?,
+    })
+}
+
+fn ai_registry() -> Registry<Action> {
+    Registry::new(vec![
+        Algorithm {
+            name: "random",
+            help: "uniformly random legal action",
+            params: &[],
+            build: |_| Ok(search(random_action)),
+        },
+        Algorithm {
+            name: "human",
+            help: "read moves from stdin (direction names, hjkl or wasd; q quits)",
+            params: &[],
+            build: |_| {
+                Ok(Box::new(|state| {
+                    let player_id = state.turn % 2;
+                    let name = if player_id == 0 { "A" } else { "B" };
+                    let prompt = format!("Player {} ({}) to move", player_id + 1, name);
+                    read_direction(&prompt, &state.legal_actions())
+                }))
+            },
+        },
+        Algorithm {
+            name: "minimax",
+            help: "minimax search",
+            params: &[ParamDef {
+                name: "depth",
+                default: None,
+                help: "search depth (default: remaining turns)",
+            }],
+            build: |params| {
+                let depth: Option<usize> = params.get_opt("depth")?;
+                Ok(search(move |state| {
+                    let depth = depth.unwrap_or(state.remaining_turns());
+                    minimax::minimax_action(state, depth) as usize
+                }))
+            },
+        },
+        Algorithm {
+            name: "alphabeta",
+            help: "alpha-beta search",
+            params: &[ParamDef {
+                name: "depth",
+                default: None,
+                help: "search depth (default: remaining turns)",
+            }],
+            build: |params| {
+                let depth: Option<usize> = params.get_opt("depth")?;
+                Ok(search(move |state| {
+                    let depth = depth.unwrap_or(state.remaining_turns());
+                    alphabeta::alphabeta_action(state, depth) as usize
+                }))
+            },
+        },
+        Algorithm {
+            name: "iterative_deepening",
+            help: "iterative deepening alpha-beta search",
+            params: &[ParamDef {
+                name: "ms",
+                default: Some("1"),
+                help: "time threshold in milliseconds",
+            }],
+            build: |params| {
+                let threshold: u128 = params.get("ms")?;
+                Ok(search(move |state| {
+                    iterative_deepening_action(state, threshold)
+                }))
+            },
+        },
+        Algorithm {
+            name: "primitive_montecarlo",
+            help: "primitive Monte Carlo search",
+            params: &[ParamDef {
+                name: "playouts",
+                default: Some("1000"),
+                help: "number of playouts",
+            }],
+            build: |params| {
+                let playout_number: usize = params.get("playouts")?;
+                Ok(search(move |state| {
+                    montecarlo::primitive_montecarlo_action(state, playout_number)
+                }))
+            },
+        },
+        Algorithm {
+            name: "mcts",
+            help: "Monte Carlo tree search",
+            params: &[
+                ParamDef {
+                    name: "playouts",
+                    default: Some("1000"),
+                    help: "number of playouts",
+                },
+                ParamDef {
+                    name: "c",
+                    default: Some("1.0"),
+                    help: "exploration constant of UCB1",
+                },
+                ParamDef {
+                    name: "expand",
+                    default: Some("10"),
+                    help: "visits before a leaf is expanded",
+                },
+            ],
+            build: |params| {
+                let playout_number: usize = params.get("playouts")?;
+                let uct_params = uct_params(params)?;
+                Ok(search(move |state| {
+                    montecarlo::mcts_action(state, playout_number, uct_params, false)
+                }))
+            },
+        },
+        Algorithm {
+            name: "mcts_time",
+            help: "Monte Carlo tree search with a time threshold",
+            params: &[
+                ParamDef {
+                    name: "ms",
+                    default: Some("1"),
+                    help: "time threshold in milliseconds",
+                },
+                ParamDef {
+                    name: "c",
+                    default: Some("1.0"),
+                    help: "exploration constant of UCB1",
+                },
+                ParamDef {
+                    name: "expand",
+                    default: Some("10"),
+                    help: "visits before a leaf is expanded",
+                },
+            ],
+            build: |params| {
+                let threshold: u128 = params.get("ms")?;
+                let uct_params = uct_params(params)?;
+                Ok(search(move |state| {
+                    montecarlo::mcts_action_with_time_threshold(state, threshold, uct_params)
+                }))
+            },
+        },
+        Algorithm {
+            name: "thunder",
+            help: "Thunder search",
+            params: &[ParamDef {
+                name: "playouts",
+                default: Some("1000"),
+                help: "number of playouts",
+            }],
+            build: |params| {
+                let playout_number: usize = params.get("playouts")?;
+                Ok(search(move |state| {
+                    thunder::thunder_search_action(state, playout_number)
+                }))
+            },
+        },
+        Algorithm {
+            name: "thunder_time",
+            help: "Thunder search with a time threshold",
+            params: &[ParamDef {
+                name: "ms",
+                default: Some("1"),
+                help: "time threshold in milliseconds",
+            }],
+            build: |params| {
+                let threshold: u128 = params.get("ms")?;
+                Ok(search(move |state| {
+                    thunder_search_action_with_time_threshold(state, threshold)
+                }))
+            },
+        },
+    ])
+}
+
+fn build_ai(spec: &str) -> Result<Ai, SpecError> {
+    let spec: AiSpec = spec.parse()?;
+    let action = ai_registry().build(&spec)?;
+
+    Ok(Ai(spec.to_string(), action))
+}
+
+const USAGE: &str = "\
+usage: ch05 [COMMAND] [OPTIONS]
+
+commands:
+    help        print this message
+    list        print the available AIs and their parameters
+    play        play one game and print every turn; use `--ai human` to play yourself
+    match       print the winning rate of the first AI over the seeds (default)
+    bench       time each AI on states sampled from random play
+    throughput  time cloning and advancing sampled states
+    replay      print every turn of a recorded game
+    tune        search the parameters of the first AI for the best winning rate against the second
+
+options:
+    --ai SPEC                 AI such as `mcts:playouts=500,c=1.4`; give two for
+                              play, match and tune (default thunder_time and
+                              iterative_deepening)
+    --height N, --width N     board size (default 5x5)
+    --end-turn N              turn limit (default 10)
+    --seed N                  play: seed of the board (default 0)
+    --seeds A..B, --games N   match, tune: seeds to play (default 0..100, tune 0..20)
+    --games N                 bench, throughput: number of sampled states (default 100)
+    --rounds N                throughput: times each state is cloned and advanced (default 1000)
+    --board FILE              play: start from a board file
+    --record FILE             play: save the game; replay: game to print
+    --states FILE             replay: write every turn to FILE as one line
+    --animate                 replay: redraw the board in place every turn, controlled from stdin
+    --delay MS                replay: time each turn stays on screen when animating (default 300)
+    --no-color                replay: animate without colors, as does setting NO_COLOR
+    --param NAME=LOW..HIGH    tune: range of a parameter, integer if both bounds are; repeatable
+    --samples N               tune: random candidates before SPSA refines the best (default 20)
+    --iterations N            tune: SPSA steps of two candidates each (default 20)
+    --tune-seed N             tune: seed of the candidates, apart from the game seeds (default 0)
+    --out FILE                tune: write the best spec to FILE
+    --format text|csv         output format (default text)";
+
+fn run(mut args: Args) -> Result<(), Box<dyn Error>> {
+    let command = args.command.take().unwrap_or_else(|| String::from("match"));
+    let config = args.take_config(DEFAULT_CONFIG)?;
+    // The characters start on either side of the middle column, at
+    // w / 2 - 1 and w / 2 + 1.
+    if config.w < 3 {
+        return Err(CliError::Invalid(String::from("`--width` must be at least 3")).into());
+    }
+    let format = args.take_or("format", OutputFormat::Text)?;
+    let mut specs = args.take_all("ai")?;
+    if specs.is_empty() {
+        specs = vec![
+            String::from("thunder_time"),
+            String::from("iterative_deepening"),
+        ];
+    }
+    let ais = specs
+        .iter()
+        .map(|spec| build_ai(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    let expect_two_ais = || {
+        if ais.len() == 2 {
+            Ok(())
+        } else {
+            Err(CliError::Invalid(format!(
+                "`{}` needs two AIs, found {}",
+                command,
+                ais.len()
+            )))
+        }
+    };
+    // Only `play` shows the board, so elsewhere `human` would wait on stdin.
+    let expect_no_human = || match ais.iter().find(|ai| ai.0 == "human") {
+        Some(ai) => Err(CliError::Invalid(format!(
+            "`{}` cannot be used with `{}`",
+            ai.0, command
+        ))),
+        None => Ok(()),
+    };
+
+    match command.as_str() {
+        "help" => println!("{}", USAGE),
+        "list" => print!("{}", ai_registry().describe()),
+        "play" => {
+            expect_two_ais()?;
+            let seed = args.take_or("seed", 0)?;
+            let board = args.take::<String>("board")?;
+            let record_path = args.take::<String>("record")?;
+            args.finish()?;
+
+            init_random_generator(seed);
+            let state = match board {
+                Some(path) => AlternateMazeState::from_board(&Board::load(path)?)?,
+                None => AlternateMazeState::new(config),
+            };
+            let board = state.to_board();
+            let actions = play_game(state, &ais, format);
+            if let Some(path) = record_path {
+                let record = GameRecord {
+                    board,
+                    actions: actions.into_iter().map(|act| vec![act]).collect(),
+                };
+                record.save(path)?;
+            }
+        }
+        "match" => {
+            expect_two_ais()?;
+            expect_no_human()?;
+            let seeds = args.take_seeds(100)?;
+            args.finish()?;
+
+            if format == OutputFormat::Csv {
+                println!("seed,first_ai_plays_second,first_ai_score");
+            }
+            test_first_player_win_rate(config, &ais, seeds, format);
+        }
+        "tune" => {
+            expect_two_ais()?;
+            expect_no_human()?;
+            let seeds = args.take_seeds(20)?;
+            let options = TuneOptions::take(&mut args)?;
+            args.finish()?;
+
+            if format == OutputFormat::Csv {
+                println!("ai,win_rate");
+            }
+            let base: AiSpec = specs[0].parse()?;
+            let tuned = tune::tune(&base, &options, |spec| {
+                let pair = [build_ai(&spec.to_string())?, build_ai(&specs[1])?];
+                let mut win_rate = 0.0;
+                for seed in seeds.clone() {
+                    for (_, win_rate_point) in play_both_sides(config, &pair, seed) {
+                        win_rate += win_rate_point as f64;
+                    }
+                }
+                win_rate /= ((seeds.end - seeds.start) * 2) as f64;
+                match format {
+                    OutputFormat::Text => {
+                        println!(
+                            "Winning rate of {} to {} : {}",
+                            pair[0].0, pair[1].0, win_rate
+                        )
+                    }
+                    OutputFormat::Csv => println!("{},{}", csv_field(&pair[0].0), win_rate),
+                }
+                Ok::<_, SpecError>(win_rate)
+            })?;
+            if format == OutputFormat::Text {
+                println!("{}", tuned);
+            }
+            if let Some(path) = &options.out {
+                tuned.save(path)?;
+            }
+        }
+        "throughput" => {
+            let game_number = args.take_at_least("games", 100, 1)?;
+            let rounds = args.take_at_least("rounds", 1000, 1)?;
+            args.finish()?;
+
+            let states = get_sample_states(config, game_number);
+            let actions: Vec<_> = states.iter().map(random_action).collect();
+            measure_throughput(&states, rounds, |i, state| state.advance(actions[i])).print(format);
+        }
+        "bench" => {
+            expect_no_human()?;
+            let game_number = args.take_at_least("games", 100, 1)?;
+            args.finish()?;
+
+            let states = get_sample_states(config, game_number);
+            if format == OutputFormat::Csv {
+                println!("ai,states,ms");
+            }
+            for ai in &ais {
+                calc_execution_speed(ai, &states, format);
+            }
+        }
+        "replay" => {
+            let path = args
+                .take::<String>("record")?
+                .ok_or(CliError::MissingValue(String::from("record")))?;
+            let animation = Animation::take(&mut args)?;
+            let states_path = args.take::<String>("states")?;
+            args.finish()?;
+
+            let record = GameRecord::load(path)?;
+            let turns = replay(&record)?;
+            let frames: Vec<Frame> = turns.iter().map(|(state, act)| state.frame(*act)).collect();
+            for (state, act) in &turns {
+                match (animation, format, act) {
+                    (Some(_), _, _) => {}
+                    (None, OutputFormat::Text, _) => println!("{}", state),
+                    (None, OutputFormat::Csv, None) => println!("turn,player,action"),
+                    // The player who moved is the one not to move now.
+                    (None, OutputFormat::Csv, Some(act)) => {
+                        println!("{},{},{}", state.turn, 2 - state.turn % 2, act)
+                    }
+                }
+            }
+            let (state, _) = turns.last().unwrap();
+            if let Some(path) = states_path {
+                animate::save_lines(path, &frames)?;
+            }
+            if let Some(animation) = animation {
+                animation.play(&frames)?;
+            } else if state.is_done() && format == OutputFormat::Text {
+                print_winner(state);
+            }
+        }
+        _ => return Err(CliError::UnknownCommand(command).into()),
+    }
+
+    Ok(())
+}
+
 fn main() {
-    let ais = vec![
-        Ai(
-            String::from("thunderSearchActionWithTimeThreshold 1ms"),
-            Box::new(|state| thunder_search_action_with_time_threshold(state, 1)),
-        ),
-        Ai(
-            String::from("iterativeDeepening 1ms"),
-            Box::new(|state| iterative_deepening_action(state, 1)),
-        ),
-    ];
-    test_first_player_win_rate(ais, 100);
+    let result = Args::parse(std::env::args().skip(1))
+        .map_err(|e| e.into())
+        .and_then(run);
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        if e.is::<CliError>() {
+            eprintln!("{}", USAGE);
+        }
+        std::process::exit(2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD: &str = "\
+3 4 4
+2
+1 0
+1 3
+1 2 3 4
+5 0 7 8
+9 1 2 3
+";
+
+    #[test]
+    fn display_draws_the_board_after_a_move() {
+        let board: Board = BOARD.parse().unwrap();
+        let mut state = AlternateMazeState::from_board(&board).unwrap();
+        state.advance(2);
+
+        assert_eq!(
+            state.to_string(),
+            "turn: 1\nscore (0): 0\nx: 1, y: 1\nscore (1): 0\nx: 1, y: 3\n1234\n5A7B\n9123\n"
+        );
+    }
+
+    #[test]
+    fn replay_follows_a_recorded_game() {
+        init_random_generator(0);
+        let mut state = AlternateMazeState::new(DEFAULT_CONFIG);
+        let board = state.to_board();
+        let mut actions = Vec::new();
+        let mut frames = vec![state.frame(None)];
+        while !state.is_done() {
+            let act = random_action(&state);
+            state.advance(act);
+            actions.push(vec![act]);
+            frames.push(state.frame(Some(act)));
+        }
+        let record = GameRecord { board, actions };
+
+        let record: GameRecord = record.to_string().parse().unwrap();
+        let replayed: Vec<Frame> = replay(&record)
+            .unwrap()
+            .iter()
+            .map(|(state, act)| state.frame(*act))
+            .collect();
+
+        assert_eq!(replayed, frames);
+    }
 }
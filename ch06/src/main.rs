@@ -1,17 +1,41 @@
 use std::cmp::Ordering;
-
-use common::{get_random, init_random_generator};
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::time::Instant;
+
+use common::animate::{self, Animation, Cell, Frame};
+use common::board::{Board, BoardError, GameRecord};
+use common::cli::{csv_field, read_direction, Args, CliError, OutputFormat, DIRECTION_NAMES};
+use common::grid::{Grid, PointGrid};
+use common::spec::{AiSpec, Algorithm, ParamDef, Params, Registry, SpecError};
+use common::tune::{self, TuneOptions};
+use common::{get_random, init_random_generator, measure_throughput, GameConfig};
 
 use alternate_motecarlo::mcts_action;
 use montecarlo::duct_action;
 
-const H: usize = 5;
-const W: usize = 5;
-const END_TURN: usize = 20;
+const DEFAULT_CONFIG: GameConfig = GameConfig::new(5, 5, 20, 2);
 #[allow(non_upper_case_globals)]
-const dstr: &[&str] = &["DOWN", "UP", "RIGHT", "LEFT"];
+const dstr: &[&str] = &DIRECTION_NAMES;
+
+/// Picks the action of one player, or `None` when a human quits.
+type PlayerAction = Box<dyn Fn(&SimultaneousMazeState) -> Option<usize>>;
+
+struct Ai(String, PlayerAction);
+
+impl Ai {
+    /// The action of an AI that never quits, as every AI but `human`.
+    fn action(&self, state: &SimultaneousMazeState) -> usize {
+        self.1(state).expect("only a human player quits")
+    }
+}
 
-struct Ai(String, Box<dyn Fn(&SimultaneousMazeState) -> usize>);
+#[derive(Debug, Clone, Copy)]
+pub struct UctParams {
+    c: f32,
+    expand_threshold: usize,
+}
 
 #[derive(Debug)]
 enum WinningStatus {
@@ -37,16 +61,17 @@ impl Character {
         }
     }
 
-    fn advance(&mut self, dx: i32, dy: i32, points: &[Vec<usize>]) {
+    fn advance(&mut self, dx: i32, dy: i32, points: &PointGrid) {
         self.x += dx;
         self.y += dy;
-        self.game_score += points[self.x as usize][self.y as usize];
+        self.game_score += points.get(self.x as usize, self.y as usize);
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct SimultaneousMazeState {
-    points: Vec<Vec<usize>>,
+    config: GameConfig,
+    points: PointGrid,
     turn: usize,
     characters: Vec<Character>,
 }
@@ -57,33 +82,62 @@ impl SimultaneousMazeState {
     #[allow(non_upper_case_globals)]
     const dy: [i32; 4] = [0, 0, 1, -1];
 
-    fn new() -> Self {
-        let mut points = vec![vec![0; H]; W];
+    fn new(config: GameConfig) -> Self {
+        let (h, w) = (config.h, config.w);
+        let mut points = Grid::new(h, w, 0);
 
-        for i in 0..H {
-            for j in 0..W / 2 + 1 {
+        for i in 0..h {
+            for j in 0..w / 2 + 1 {
                 let point = get_random(10);
-                if i == H / 2 && (j == W / 2 - 1 || j == W / 2 + 1) {
+                if i == h / 2 && (j == w / 2 - 1 || j == w / 2 + 1) {
                     continue;
                 }
                 let mut tj = j;
-                points[i][tj] = point;
-                tj = W - 1 - j;
-                points[i][tj] = point;
+                points[(i, tj)] = point;
+                tj = w - 1 - j;
+                points[(i, tj)] = point;
             }
         }
         Self {
-            points,
+            config,
+            points: PointGrid::new(points),
             turn: 0,
             characters: vec![
-                Character::new(H as i32 / 2, W as i32 / 2 - 1),
-                Character::new(H as i32 / 2, W as i32 / 2 + 1),
+                Character::new(h as i32 / 2, w as i32 / 2 - 1),
+                Character::new(h as i32 / 2, w as i32 / 2 + 1),
             ],
         }
     }
 
+    fn from_board(board: &Board) -> Result<Self, BoardError> {
+        board.expect_characters(2)?;
+        board.expect_no_walls()?;
+
+        Ok(Self {
+            config: board.config(),
+            points: PointGrid::from_rows(&board.points),
+            turn: 0,
+            characters: board
+                .characters
+                .iter()
+                .map(|&(x, y)| Character::new(x, y))
+                .collect(),
+        })
+    }
+
+    fn to_board(&self) -> Board {
+        Board {
+            h: self.config.h,
+            w: self.config.w,
+            end_turn: self.config.end_turn - self.turn,
+            characters: self.characters.iter().map(|c| (c.x, c.y)).collect(),
+            points: self.points.to_rows(),
+            walls: Vec::new(),
+        }
+    }
+
     const fn is_done(&self) -> bool {
-        self.turn == END_TURN
+        self.turn == self.config.end_turn
     }
 
     fn advance(&mut self, action0: usize, action1: usize) {
@@ -98,7 +152,8 @@ impl SimultaneousMazeState {
             &self.points,
         );
         for character in &self.characters {
-            self.points[character.x as usize][character.y as usize] = 0;
+            self.points
+                .consume(character.x as usize, character.y as usize);
         }
         self.turn += 1;
     }
@@ -109,7 +164,7 @@ impl SimultaneousMazeState {
         for i in 0..4 {
             let nx = character.x + Self::dx[i];
             let ny = character.y + Self::dy[i];
-            if nx >= 0 && nx < H as i32 && ny >= 0 && ny < W as i32 {
+            if self.config.is_inside(nx, ny) {
                 actions.push(i);
             }
         }
@@ -139,52 +194,78 @@ impl SimultaneousMazeState {
         }
     }
 
-    fn to_string(&self) {
-        println!("turn: {}", self.turn);
+    /// The board as drawn by `to_string`, with the last actions named under
+    /// the scores.
+    fn frame(&self, last_actions: Option<(usize, usize)>) -> Frame {
+        let notes = match last_actions {
+            Some((action0, action1)) => {
+                vec![format!("actions {} {}", dstr[action0], dstr[action1])]
+            }
+            None => Vec::new(),
+        };
+        let cells = (0..self.config.h)
+            .map(|h| {
+                (0..self.config.w)
+                    .map(|w| {
+                        let character = self
+                            .characters
+                            .iter()
+                            .rposition(|c| c.x as usize == h && c.y as usize == w);
+                        match character {
+                            Some(player) => Cell::Character {
+                                label: (b'A' + player as u8) as char,
+                                player,
+                            },
+                            None if self.points.get(h, w) > 0 => {
+                                Cell::Points(self.points.get(h, w))
+                            }
+                            None => Cell::Empty,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Frame {
+            turn: self.turn,
+            scores: self
+                .characters
+                .iter()
+                .map(|character| character.game_score as i64)
+                .collect(),
+            notes,
+            cells,
+        }
+    }
+}
+
+impl fmt::Display for SimultaneousMazeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "turn: {}", self.turn)?;
         for player_id in 0..self.characters.len() {
-            println!(
+            writeln!(
+                f,
                 "score({}): {}",
                 player_id, self.characters[player_id].game_score
-            );
-        }
-        for h in 0..H {
-            for w in 0..W {
-                let mut is_written = false;
-                for player_id in 0..self.characters.len() {
-                    let character = &self.characters[player_id];
-                    if character.x as usize == h && character.y as usize == w {
-                        if player_id == 0 {
-                            print!("A");
-                        } else {
-                            print!("B");
-                        }
-                        is_written = true;
-                    }
-                }
-
-                if !is_written {
-                    if self.points[h][w] > 0 {
-                        print!("{}", self.points[h][w]);
-                    } else {
-                        print!(".");
-                    }
-                }
-            }
-            println!();
+            )?;
+        }
+        for row in self.frame(None).rows() {
+            writeln!(f, "{}", row)?;
         }
-        println!();
+
+        Ok(())
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct AlternateMazeState {
-    points: Vec<Vec<usize>>,
+    config: GameConfig,
+    points: PointGrid,
     turn: usize,
     characters: Vec<Character>,
 }
 
 impl AlternateMazeState {
-    const END_TURN: usize = END_TURN * 2;
     #[allow(non_upper_case_globals)]
     const dx: [i32; 4] = [1, -1, 0, 0];
     #[allow(non_upper_case_globals)]
@@ -192,6 +273,7 @@ impl AlternateMazeState {
 
     fn new(base_state: &SimultaneousMazeState, player_id: usize) -> Self {
         Self {
+            config: base_state.config,
             points: base_state.points.clone(),
             turn: base_state.turn * 2,
             characters: if player_id == 0 {
@@ -203,13 +285,14 @@ impl AlternateMazeState {
     }
 
     const fn is_done(&self) -> bool {
-        self.turn == Self::END_TURN
+        self.turn == self.config.end_turn * 2
     }
 
     fn advance(&mut self, action: usize) {
         let character = self.characters.get_mut(0).unwrap();
         character.advance(Self::dx[action], Self::dy[action], &self.points);
-        self.points[character.x as usize][character.y as usize] = 0;
+        self.points
+            .consume(character.x as usize, character.y as usize);
         self.turn += 1;
         self.characters.swap(0, 1);
     }
@@ -220,7 +303,7 @@ impl AlternateMazeState {
         for i in 0..4 {
             let nx = character.x + Self::dx[i];
             let ny = character.y + Self::dy[i];
-            if nx >= 0 && nx < H as i32 && ny >= 0 && ny < W as i32 {
+            if self.config.is_inside(nx, ny) {
                 actions.push(i);
             }
         }
@@ -245,10 +328,7 @@ impl AlternateMazeState {
 
 #[allow(dead_code)]
 mod montecarlo {
-    use super::{get_random, random_action, SimultaneousMazeState, WinningStatus};
-
-    const C: f32 = 1.0;
-    const EXPAND_THRESHOLD: usize = 5;
+    use super::{get_random, random_action, SimultaneousMazeState, UctParams, WinningStatus};
 
     // The view from the player0
     fn playout(state: &mut SimultaneousMazeState) -> f32 {
@@ -305,11 +385,12 @@ mod montecarlo {
         state: &SimultaneousMazeState,
         player_id: usize,
         playout_number: usize,
+        params: UctParams,
     ) -> usize {
         let mut root_node = Node::new(state);
         root_node.expand();
         for _ in 0..playout_number {
-            root_node.evaluate();
+            root_node.evaluate(params);
         }
         let legal_actions = state.legal_actions(player_id);
         let i_size = root_node.child_nodeses.len();
@@ -366,7 +447,7 @@ mod montecarlo {
             }
         }
 
-        fn evaluate(&mut self) -> f32 {
+        fn evaluate(&mut self, params: UctParams) -> f32 {
             if self.state.is_done() {
                 let mut value = 0.5;
                 match self.state.get_winning_status() {
@@ -384,14 +465,14 @@ mod montecarlo {
                 self.w += value;
                 self.n += 1;
 
-                if self.n == EXPAND_THRESHOLD {
+                if self.n == params.expand_threshold {
                     self.expand();
                 }
 
                 value
             } else {
-                let (l_index, r_index) = self.next_child_node();
-                let value = self.child_nodeses[l_index][r_index].evaluate();
+                let (l_index, r_index) = self.next_child_node(params.c);
+                let value = self.child_nodeses[l_index][r_index].evaluate(params);
                 self.w += value;
                 self.n += 1;
                 value
@@ -413,7 +494,7 @@ mod montecarlo {
             }
         }
 
-        fn next_child_node(&mut self) -> (usize, usize) {
+        fn next_child_node(&mut self, c: f32) -> (usize, usize) {
             for (i, child_nodes) in self.child_nodeses.iter().enumerate() {
                 for (j, child_node) in child_nodes.iter().enumerate() {
                     if child_node.n == 0 {
@@ -438,7 +519,7 @@ mod montecarlo {
                     w += child_node.w;
                     n += child_node.n;
                 }
-                let ucb1_value = w / n as f32 + C * (2.0 * t.ln() / n as f32).sqrt();
+                let ucb1_value = w / n as f32 + c * (2.0 * t.ln() / n as f32).sqrt();
                 if ucb1_value > best_value {
                     best_is.0 = i as i32;
                     best_value = ucb1_value;
@@ -455,7 +536,7 @@ mod montecarlo {
                     w += child_node.w;
                     n += child_node.n;
                 }
-                let ucb1_value = 1.0 - w / n as f32 + C * (2.0 * t.ln() / n as f32).sqrt();
+                let ucb1_value = 1.0 - w / n as f32 + c * (2.0 * t.ln() / n as f32).sqrt();
                 if ucb1_value > best_value {
                     best_is.1 = j as i32;
                     best_value = ucb1_value;
@@ -468,10 +549,7 @@ mod montecarlo {
 }
 
 mod alternate_motecarlo {
-    use super::{get_random, AlternateMazeState, SimultaneousMazeState, WinningStatus};
-
-    const C: f32 = 1.0;
-    const EXPAND_THRESHOLD: usize = 10;
+    use super::{get_random, AlternateMazeState, SimultaneousMazeState, UctParams, WinningStatus};
 
     fn random_action(state: &AlternateMazeState) -> usize {
         let legal_actions = state.legal_actions();
@@ -508,7 +586,7 @@ mod alternate_motecarlo {
             }
         }
 
-        fn evaluate(&mut self) -> f32 {
+        fn evaluate(&mut self, params: UctParams) -> f32 {
             if self.state.is_done() {
                 let mut value = 0.5;
                 match self.state.get_winning_status() {
@@ -525,18 +603,18 @@ mod alternate_motecarlo {
                 let value = playout(&mut state_copy);
                 self.w += value;
                 self.n += 1;
-                if self.n == EXPAND_THRESHOLD {
+                if self.n == params.expand_threshold {
                     self.expand();
                 }
                 value
             } else {
-                let next_child_node_index = self.next_child_node();
+                let next_child_node_index = self.next_child_node(params.c);
                 let value = 1.0
                     - self
                         .child_nodes
                         .get_mut(next_child_node_index)
                         .unwrap()
-                        .evaluate();
+                        .evaluate(params);
                 self.w += value;
                 self.n += 1;
                 value
@@ -552,7 +630,7 @@ mod alternate_motecarlo {
             }
         }
 
-        fn next_child_node(&mut self) -> usize {
+        fn next_child_node(&mut self, c: f32) -> usize {
             let mut t = 0.0;
             for (i, child_node) in self.child_nodes.iter().enumerate() {
                 if child_node.n == 0 {
@@ -566,7 +644,7 @@ mod alternate_motecarlo {
             for i in 0..self.child_nodes.len() {
                 let child_node = &self.child_nodes[i];
                 let ucb1_value = 1.0 - child_node.w / child_node.n as f32
-                    + C * (2.0 * t.ln() / child_node.n as f32).sqrt();
+                    + c * (2.0 * t.ln() / child_node.n as f32).sqrt();
                 if ucb1_value > best_value {
                     best_value = ucb1_value;
                     best_action_index = i as i32;
@@ -581,12 +659,13 @@ mod alternate_motecarlo {
         base_state: &SimultaneousMazeState,
         player_id: usize,
         playout_number: usize,
+        params: UctParams,
     ) -> usize {
         let state = AlternateMazeState::new(base_state, player_id);
         let mut root_node = Node::new(&state);
         root_node.expand();
         for _ in 0..playout_number {
-            root_node.evaluate();
+            root_node.evaluate(params);
         }
         let legal_actions = state.legal_actions();
 
@@ -611,60 +690,500 @@ fn random_action(state: &SimultaneousMazeState, player_id: usize) -> usize {
     legal_actions[get_random(legal_actions.len())]
 }
 
-#[allow(dead_code)]
-fn play_game(ais: Vec<Ai>) {
-    let mut state = SimultaneousMazeState::new();
-    state.to_string();
+fn play_game(
+    mut state: SimultaneousMazeState,
+    ais: &[Ai],
+    format: OutputFormat,
+) -> Vec<(usize, usize)> {
+    let mut actions = Vec::new();
+    match format {
+        OutputFormat::Text => println!("{}", state),
+        OutputFormat::Csv => println!("turn,action0,action1"),
+    }
 
     while !state.is_done() {
-        let actions = (&ais[0].1(&state), &ais[1].1(&state));
-        println!("actions {} {}", dstr[*actions.0], dstr[*actions.1]);
-        state.advance(*actions.0, *actions.1);
-        state.to_string();
+        let Some(actions_now) =
+            ais[0].1(&state).and_then(|action0| Some((action0, ais[1].1(&state)?)))
+        else {
+            if format == OutputFormat::Text {
+                println!("Game stopped");
+            }
+            return actions;
+        };
+        actions.push(actions_now);
+        state.advance(actions_now.0, actions_now.1);
+        match format {
+            OutputFormat::Text => {
+                println!("actions {} {}", dstr[actions_now.0], dstr[actions_now.1]);
+                println!("{}", state);
+            }
+            OutputFormat::Csv => println!("{},{},{}", state.turn, actions_now.0, actions_now.1),
+        }
     }
-}
 
-#[allow(dead_code)]
-fn test_first_player_win_rate(ais: Vec<Ai>, game_number: usize) {
-    let mut first_player_win_rate = 0.0;
-    for i in 0..game_number {
-        init_random_generator(i as u64);
+    actions
+}
 
-        let mut state = SimultaneousMazeState::new();
-        let first_ai = &ais[0];
-        let second_ai = &ais[1];
-        loop {
-            state.advance(first_ai.1(&state), second_ai.1(&state));
-            if state.is_done() {
-                break;
-            }
+/// Plays `ais[0]` as player 0 against `ais[1]` on the board of `seed` and
+/// returns the final state with the points of player 0.
+fn play_seed(config: GameConfig, ais: &[Ai], seed: u64) -> (SimultaneousMazeState, f32) {
+    init_random_generator(seed);
+
+    let mut state = SimultaneousMazeState::new(config);
+    let first_ai = &ais[0];
+    let second_ai = &ais[1];
+    loop {
+        state.advance(first_ai.action(&state), second_ai.action(&state));
+        if state.is_done() {
+            break;
         }
-        let win_rate_point = state.get_first_player_score_for_winning_rate();
-        if win_rate_point >= 0.0 {
-            state.to_string()
+    }
+    let win_rate_point = state.get_first_player_score_for_winning_rate();
+
+    (state, win_rate_point)
+}
+
+fn test_first_player_win_rate(
+    config: GameConfig,
+    ais: &[Ai],
+    seeds: Range<u64>,
+    format: OutputFormat,
+) {
+    let game_number = seeds.end - seeds.start;
+    let mut first_player_win_rate = 0.0;
+    for (i, seed) in seeds.enumerate() {
+        let (state, win_rate_point) = play_seed(config, ais, seed);
+        match format {
+            OutputFormat::Text => println!("{}", state),
+            OutputFormat::Csv => println!("{},{}", seed, win_rate_point),
         }
         first_player_win_rate += win_rate_point;
 
-        println!("i {} w {}", i, first_player_win_rate / (i + 1) as f32);
+        if format == OutputFormat::Text {
+            println!("i {} w {}", i, first_player_win_rate / (i + 1) as f32);
+        }
     }
     first_player_win_rate /= game_number as f32;
-    println!(
-        "Winning rate of {} to {} : {}",
-        &ais[0].0, &ais[1].0, first_player_win_rate
-    );
+    if format == OutputFormat::Text {
+        println!(
+            "Winning rate of {} to {} : {}",
+            &ais[0].0, &ais[1].0, first_player_win_rate
+        );
+    }
 }
 
-fn main() {
-    let ais = vec![
-        Ai(
-            String::from("ductAction"),
-            Box::new(|state| duct_action(state, 0, 1000)),
-        ),
-        Ai(
-            String::from("mctsAction"),
-            Box::new(|state| mcts_action(state, 1, 1000)),
+fn get_sample_states(config: GameConfig, game_number: usize) -> Vec<SimultaneousMazeState> {
+    init_random_generator(0);
+
+    let mut states = Vec::new();
+    for _ in 0..game_number {
+        let mut state = SimultaneousMazeState::new(config);
+        let turn = get_random(config.end_turn);
+        for _ in 0..turn {
+            state.advance(random_action(&state, 0), random_action(&state, 1));
+        }
+        states.push(state);
+    }
+
+    states
+}
+
+fn calc_execution_speed(ai: &Ai, states: &[SimultaneousMazeState], format: OutputFormat) {
+    let start_time = Instant::now();
+    for state in states {
+        ai.action(state);
+    }
+    let diff = Instant::now() - start_time;
+    match format {
+        OutputFormat::Text => println!(
+            "{} take {} ms to process {} nodes",
+            ai.0,
+            diff.as_millis(),
+            states.len()
         ),
-    ];
+        OutputFormat::Csv => println!("{},{},{}", csv_field(&ai.0), states.len(), diff.as_millis()),
+    }
+}
+
+type Action = Box<dyn Fn(&SimultaneousMazeState, usize) -> Option<usize>>;
+
+fn search(action: impl Fn(&SimultaneousMazeState, usize) -> usize + 'static) -> Action {
+    Box::new(move |state, player_id| Some(action(state, player_id)))
+}
+
+fn uct_params(params: &Params) -> Result<UctParams, SpecError> {
+    Ok(UctParams {
+        c: params.get("c")?,
+        expand_threshold: params.get("expand")?,
+    })
+}
+
+fn ai_registry() -> Registry<Action> {
+    Registry::new(vec![
+        Algorithm {
+            name: "random",
+            help: "uniformly random legal action",
+            params: &[],
+            build: |_| Ok(search(random_action)),
+        },
+        Algorithm {
+            name: "human",
+            help: "read moves from stdin (direction names, hjkl or wasd; q quits)",
+            params: &[],
+            build: |_| {
+                Ok(Box::new(|state, player_id| {
+                    let name = if player_id == 0 { "A" } else { "B" };
+                    let prompt = format!("Player {} ({}) to move", player_id, name);
+                    read_direction(&prompt, &state.legal_actions(player_id))
+                }))
+            },
+        },
+        Algorithm {
+            name: "primitive_montecarlo",
+            help: "primitive Monte Carlo search against a random opponent",
+            params: &[ParamDef {
+                name: "playouts",
+                default: Some("1000"),
+                help: "number of playouts per action",
+            }],
+            build: |params| {
+                let playout_number: usize = params.get("playouts")?;
+                Ok(search(move |state, player_id| {
+                    montecarlo::primitive_montecarlo_action(state, player_id, playout_number)
+                }))
+            },
+        },
+        Algorithm {
+            name: "duct",
+            help: "decoupled UCT",
+            params: &[
+                ParamDef {
+                    name: "playouts",
+                    default: Some("1000"),
+                    help: "number of playouts",
+                },
+                ParamDef {
+                    name: "c",
+                    default: Some("1.0"),
+                    help: "exploration constant of UCB1",
+                },
+                ParamDef {
+                    name: "expand",
+                    default: Some("5"),
+                    help: "visits before a leaf is expanded",
+                },
+            ],
+            build: |params| {
+                let playout_number: usize = params.get("playouts")?;
+                let uct_params = uct_params(params)?;
+                Ok(search(move |state, player_id| {
+                    duct_action(state, player_id, playout_number, uct_params)
+                }))
+            },
+        },
+        Algorithm {
+            name: "mcts",
+            help: "Monte Carlo tree search on the game played alternately",
+            params: &[
+                ParamDef {
+                    name: "playouts",
+                    default: Some("1000"),
+                    help: "number of playouts",
+                },
+                ParamDef {
+                    name: "c",
+                    default: Some("1.0"),
+                    help: "exploration constant of UCB1",
+                },
+                ParamDef {
+                    name: "expand",
+                    default: Some("10"),
+                    help: "visits before a leaf is expanded",
+                },
+            ],
+            build: |params| {
+                let playout_number: usize = params.get("playouts")?;
+                let uct_params = uct_params(params)?;
+                Ok(search(move |state, player_id| {
+                    mcts_action(state, player_id, playout_number, uct_params)
+                }))
+            },
+        },
+    ])
+}
 
-    test_first_player_win_rate(ais, 500);
+fn build_ai(spec: &str, player_id: usize) -> Result<Ai, SpecError> {
+    let spec: AiSpec = spec.parse()?;
+    let action = ai_registry().build(&spec)?;
+
+    Ok(Ai(
+        spec.to_string(),
+        Box::new(move |state| action(state, player_id)),
+    ))
+}
+
+const USAGE: &str = "\
+usage: ch06 [COMMAND] [OPTIONS]
+
+commands:
+    help        print this message
+    list        print the available AIs and their parameters
+    play        play one game and print every turn; use `--ai human` to play yourself
+    match       print the winning rate of the first AI over the seeds (default)
+    bench       time each AI on states sampled from random play
+    throughput  time cloning and advancing sampled states
+    replay      print every turn of a recorded game
+    tune        search the parameters of the first AI for the best winning rate against the second
+
+options:
+    --ai SPEC                 AI such as `duct:playouts=500,c=1.4`; the first plays
+                              player 0 and the second player 1 (default duct and mcts)
+    --height N, --width N     board size (default 5x5)
+    --end-turn N              turn limit (default 20)
+    --seed N                  play: seed of the board (default 0)
+    --seeds A..B, --games N   match, tune: seeds to play (default 0..500, tune 0..100)
+    --games N                 bench, throughput: number of sampled states (default 100)
+    --rounds N                throughput: times each state is cloned and advanced (default 1000)
+    --board FILE              play: start from a board file
+    --record FILE             play: save the game; replay: game to print
+    --states FILE             replay: write every turn to FILE as one line
+    --animate                 replay: redraw the board in place every turn, controlled from stdin
+    --delay MS                replay: time each turn stays on screen when animating (default 300)
+    --no-color                replay: animate without colors, as does setting NO_COLOR
+    --param NAME=LOW..HIGH    tune: range of a parameter, integer if both bounds are; repeatable
+    --samples N               tune: random candidates before SPSA refines the best (default 20)
+    --iterations N            tune: SPSA steps of two candidates each (default 20)
+    --tune-seed N             tune: seed of the candidates, apart from the game seeds (default 0)
+    --out FILE                tune: write the best spec to FILE
+    --format text|csv         output format (default text)";
+
+fn run(mut args: Args) -> Result<(), Box<dyn Error>> {
+    let command = args.command.take().unwrap_or_else(|| String::from("match"));
+    let config = args.take_config(DEFAULT_CONFIG)?;
+    // The characters start on either side of the middle column, at
+    // w / 2 - 1 and w / 2 + 1.
+    if config.w < 3 {
+        return Err(CliError::Invalid(String::from("`--width` must be at least 3")).into());
+    }
+    let format = args.take_or("format", OutputFormat::Text)?;
+    let mut specs = args.take_all("ai")?;
+    if specs.is_empty() {
+        specs = vec![String::from("duct"), String::from("mcts")];
+    }
+    let ais = specs
+        .iter()
+        .enumerate()
+        .map(|(player_id, spec)| build_ai(spec, player_id % 2))
+        .collect::<Result<Vec<_>, _>>()?;
+    let expect_two_ais = || {
+        if ais.len() == 2 {
+            Ok(())
+        } else {
+            Err(CliError::Invalid(format!(
+                "`{}` needs two AIs, found {}",
+                command,
+                ais.len()
+            )))
+        }
+    };
+    // Only `play` shows the board, so elsewhere `human` would wait on stdin.
+    let expect_no_human = || match ais.iter().find(|ai| ai.0 == "human") {
+        Some(ai) => Err(CliError::Invalid(format!(
+            "`{}` cannot be used with `{}`",
+            ai.0, command
+        ))),
+        None => Ok(()),
+    };
+
+    match command.as_str() {
+        "help" => println!("{}", USAGE),
+        "list" => print!("{}", ai_registry().describe()),
+        "play" => {
+            expect_two_ais()?;
+            let seed = args.take_or("seed", 0)?;
+            let board = args.take::<String>("board")?;
+            let record_path = args.take::<String>("record")?;
+            args.finish()?;
+
+            init_random_generator(seed);
+            let state = match board {
+                Some(path) => SimultaneousMazeState::from_board(&Board::load(path)?)?,
+                None => SimultaneousMazeState::new(config),
+            };
+            let board = state.to_board();
+            let actions = play_game(state, &ais, format);
+            if let Some(path) = record_path {
+                let record = GameRecord {
+                    board,
+                    actions: actions.into_iter().map(|(a0, a1)| vec![a0, a1]).collect(),
+                };
+                record.save(path)?;
+            }
+        }
+        "match" => {
+            expect_two_ais()?;
+            expect_no_human()?;
+            let seeds = args.take_seeds(500)?;
+            args.finish()?;
+
+            if format == OutputFormat::Csv {
+                println!("seed,first_player_score");
+            }
+            test_first_player_win_rate(config, &ais, seeds, format);
+        }
+        "tune" => {
+            expect_two_ais()?;
+            expect_no_human()?;
+            let seeds = args.take_seeds(100)?;
+            let options = TuneOptions::take(&mut args)?;
+            args.finish()?;
+
+            if format == OutputFormat::Csv {
+                println!("ai,win_rate");
+            }
+            let base: AiSpec = specs[0].parse()?;
+            let tuned = tune::tune(&base, &options, |spec| {
+                let pair = [build_ai(&spec.to_string(), 0)?, build_ai(&specs[1], 1)?];
+                let mut win_rate = 0.0;
+                for seed in seeds.clone() {
+                    win_rate += play_seed(config, &pair, seed).1 as f64;
+                }
+                win_rate /= (seeds.end - seeds.start) as f64;
+                match format {
+                    OutputFormat::Text => {
+                        println!(
+                            "Winning rate of {} to {} : {}",
+                            pair[0].0, pair[1].0, win_rate
+                        )
+                    }
+                    OutputFormat::Csv => println!("{},{}", csv_field(&pair[0].0), win_rate),
+                }
+                Ok::<_, SpecError>(win_rate)
+            })?;
+            if format == OutputFormat::Text {
+                println!("{}", tuned);
+            }
+            if let Some(path) = &options.out {
+                tuned.save(path)?;
+            }
+        }
+        "throughput" => {
+            let game_number = args.take_at_least("games", 100, 1)?;
+            let rounds = args.take_at_least("rounds", 1000, 1)?;
+            args.finish()?;
+
+            let states = get_sample_states(config, game_number);
+            let actions: Vec<_> = states
+                .iter()
+                .map(|state| (random_action(state, 0), random_action(state, 1)))
+                .collect();
+            measure_throughput(&states, rounds, |i, state| {
+                state.advance(actions[i].0, actions[i].1)
+            })
+            .print(format);
+        }
+        "bench" => {
+            expect_no_human()?;
+            let game_number = args.take_at_least("games", 100, 1)?;
+            args.finish()?;
+
+            let states = get_sample_states(config, game_number);
+            if format == OutputFormat::Csv {
+                println!("ai,states,ms");
+            }
+            for ai in &ais {
+                calc_execution_speed(ai, &states, format);
+            }
+        }
+        "replay" => {
+            let path = args
+                .take::<String>("record")?
+                .ok_or(CliError::MissingValue(String::from("record")))?;
+            let animation = Animation::take(&mut args)?;
+            let states_path = args.take::<String>("states")?;
+            args.finish()?;
+
+            let record = GameRecord::load(path)?;
+            let mut state = SimultaneousMazeState::from_board(&record.board)?;
+            let mut frames = vec![state.frame(None)];
+            match (animation, format) {
+                (Some(_), _) => {}
+                (None, OutputFormat::Text) => println!("{}", state),
+                (None, OutputFormat::Csv) => println!("turn,action0,action1"),
+            }
+            for actions in &record.actions {
+                let (action0, action1) = match actions[..] {
+                    [action0, action1] => (action0, action1),
+                    _ => return Err(format!("expected two actions at turn {}", state.turn).into()),
+                };
+                if state.is_done()
+                    || !state.legal_actions(0).contains(&action0)
+                    || !state.legal_actions(1).contains(&action1)
+                {
+                    return Err(format!("illegal actions at turn {}", state.turn).into());
+                }
+                state.advance(action0, action1);
+                frames.push(state.frame(Some((action0, action1))));
+                match (animation, format) {
+                    (Some(_), _) => {}
+                    (None, OutputFormat::Text) => {
+                        println!("actions {} {}", dstr[action0], dstr[action1]);
+                        println!("{}", state);
+                    }
+                    (None, OutputFormat::Csv) => {
+                        println!("{},{},{}", state.turn, action0, action1)
+                    }
+                }
+            }
+            if let Some(path) = states_path {
+                animate::save_lines(path, &frames)?;
+            }
+            if let Some(animation) = animation {
+                animation.play(&frames)?;
+            }
+        }
+        _ => return Err(CliError::UnknownCommand(command).into()),
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let result = Args::parse(std::env::args().skip(1))
+        .map_err(|e| e.into())
+        .and_then(run);
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        if e.is::<CliError>() {
+            eprintln!("{}", USAGE);
+        }
+        std::process::exit(2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD: &str = "\
+3 4 4
+2
+1 0
+1 3
+1 2 3 4
+5 0 7 8
+9 1 2 3
+";
+
+    #[test]
+    fn display_draws_the_board_after_a_turn() {
+        let board: Board = BOARD.parse().unwrap();
+        let mut state = SimultaneousMazeState::from_board(&board).unwrap();
+        state.advance(0, 3);
+
+        assert_eq!(
+            state.to_string(),
+            "turn: 1\nscore(0): 9\nscore(1): 7\n1234\n5.B8\nA123\n"
+        );
+    }
 }
@@ -0,0 +1,300 @@
+//! Animated replay of games in a terminal.
+//!
+//! Every turn of a game becomes a [`Frame`], which is redrawn in place with
+//! ANSI escapes. A thread reads commands from stdin and sends them over a
+//! channel. The standard library cannot put the terminal in raw mode, so a
+//! command is a line: each character of it is one command, and an empty line
+//! pauses or resumes.
+//!
+//! ```text
+//! p or Enter  pause / resume      + or f  twice as fast
+//! n           next turn           - or s  twice as slow
+//! b           previous turn       q       quit
+//! ```
+//!
+//! Frames also serialize to one line each with [`Frame::to_line`], which
+//! `replay --states FILE` writes for every turn.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use crate::cli::{Args, CliError};
+
+const MIN_DELAY: Duration = Duration::from_millis(10);
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// Colors of the characters, by player or by moving order.
+const PLAYER_COLORS: [&str; 6] = ["1;31", "1;34", "1;32", "1;35", "1;36", "1;33"];
+
+fn player_color(player: usize) -> &'static str {
+    PLAYER_COLORS[player % PLAYER_COLORS.len()]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cell {
+    Empty,
+    Wall,
+    /// A collected cell that gets its points back later.
+    Regrowing,
+    Points(usize),
+    /// A character, drawn as `label` in the color of `player`.
+    Character {
+        label: char,
+        player: usize,
+    },
+}
+
+impl Cell {
+    fn symbol(self) -> String {
+        match self {
+            Cell::Empty => String::from("."),
+            Cell::Wall => String::from("#"),
+            Cell::Regrowing => String::from(","),
+            Cell::Points(points) => points.to_string(),
+            Cell::Character { label, .. } => label.to_string(),
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Cell::Empty => "2",
+            Cell::Wall => "90",
+            Cell::Regrowing => "2;32",
+            Cell::Points(0..=3) => "37",
+            Cell::Points(4..=6) => "33",
+            Cell::Points(_) => "1;33",
+            Cell::Character { player, .. } => player_color(player),
+        }
+    }
+}
+
+/// The board and scores after one turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub turn: usize,
+    /// The score of a one-player game, or of each player as A, B, ...
+    pub scores: Vec<i64>,
+    /// Lines printed under the scores, such as the actions just taken.
+    pub notes: Vec<String>,
+    pub cells: Vec<Vec<Cell>>,
+}
+
+impl Frame {
+    /// The frame on one line, such as `turn=3 score=21,28 board=7.A2/11.B`,
+    /// with `/` between the rows. Notes are left out.
+    pub fn to_line(&self) -> String {
+        let scores: Vec<String> = self.scores.iter().map(i64::to_string).collect();
+        let rows: Vec<String> = self.rows().collect();
+
+        format!(
+            "turn={} score={} board={}",
+            self.turn,
+            scores.join(","),
+            rows.join("/")
+        )
+    }
+
+    /// The rows of the board as plain text, top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = String> + '_ {
+        self.cells
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+    }
+
+    fn render(&self, color: bool) -> String {
+        let paint = |text: &str, code: &str| {
+            if color {
+                format!("\x1b[{}m{}\x1b[0m", code, text)
+            } else {
+                text.to_string()
+            }
+        };
+
+        let mut out = format!("turn: {}\n", self.turn);
+        if let [score] = self.scores[..] {
+            out += &format!("score: {}\n", score);
+        } else {
+            let scores: Vec<String> = self
+                .scores
+                .iter()
+                .enumerate()
+                .map(|(player, score)| {
+                    let label = ((b'A' + (player % 26) as u8) as char).to_string();
+                    format!("{} {}", paint(&label, player_color(player)), score)
+                })
+                .collect();
+            out += &format!("score: {}\n", scores.join("  "));
+        }
+        for note in &self.notes {
+            out += note;
+            out.push('\n');
+        }
+        for row in &self.cells {
+            for &cell in row {
+                out += &paint(&cell.symbol(), cell.color());
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+/// The frame as an animation draws it, without colors.
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+/// Writes [`Frame::to_line`] of every frame to `path`, one per line.
+pub fn save_lines(path: impl AsRef<Path>, frames: &[Frame]) -> io::Result<()> {
+    let lines: String = frames.iter().map(|frame| frame.to_line() + "\n").collect();
+    fs::write(path, lines)
+}
+
+enum Control {
+    Toggle,
+    Next,
+    Back,
+    Faster,
+    Slower,
+    Quit,
+}
+
+/// Starts the thread reading commands from stdin. The channel disconnects at
+/// the end of input.
+fn spawn_controls() -> Receiver<Control> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            let controls: Vec<Control> = if line.trim().is_empty() {
+                vec![Control::Toggle]
+            } else {
+                line.chars()
+                    .filter_map(|c| match c.to_ascii_lowercase() {
+                        'p' | ' ' => Some(Control::Toggle),
+                        'n' => Some(Control::Next),
+                        'b' => Some(Control::Back),
+                        '+' | 'f' => Some(Control::Faster),
+                        '-' | 's' => Some(Control::Slower),
+                        'q' => Some(Control::Quit),
+                        _ => None,
+                    })
+                    .collect()
+            };
+            for control in controls {
+                if sender.send(control).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    receiver
+}
+
+/// How `replay --animate` plays the frames, read by [`Animation::take`].
+#[derive(Debug, Clone, Copy)]
+pub struct Animation {
+    /// Time each frame stays on screen while playing.
+    pub delay: Duration,
+    pub color: bool,
+}
+
+impl Animation {
+    /// Reads the `--animate` flag with `--delay MS` (default 300) and
+    /// `--no-color`. Returns `None` without `--animate`, leaving the other
+    /// two options to be reported as unknown. Colors are also off when
+    /// `NO_COLOR` is set.
+    pub fn take(args: &mut Args) -> Result<Option<Animation>, CliError> {
+        if !args.take_flag("animate")? {
+            return Ok(None);
+        }
+        let delay = args.take_or("delay", 300)?;
+        let no_color = args.take_flag("no-color")?;
+
+        Ok(Some(Animation {
+            delay: Duration::from_millis(delay).clamp(MIN_DELAY, MAX_DELAY),
+            color: !no_color && std::env::var_os("NO_COLOR").is_none(),
+        }))
+    }
+
+    /// Plays `frames` until the last one has been shown for a delay or `q`
+    /// is read. Stepping pauses; when stdin ends the animation plays on.
+    pub fn play(&self, frames: &[Frame]) -> io::Result<()> {
+        if frames.is_empty() {
+            return Ok(());
+        }
+        let controls = spawn_controls();
+        let last = frames.len() - 1;
+        let mut stdout = io::stdout().lock();
+        // Clear the screen once and hide the cursor; later frames only go
+        // back to the top left corner and clear what is below.
+        write!(stdout, "\x1b[2J\x1b[?25l")?;
+
+        let mut index = 0;
+        let mut delay = self.delay;
+        let mut paused = false;
+        let mut input_open = true;
+        let result = loop {
+            let status = format!(
+                "frame {}/{}  delay {} ms{}\n\
+                 Enter pause  n next  b back  + faster  - slower  q quit",
+                index + 1,
+                frames.len(),
+                delay.as_millis(),
+                if paused { "  [paused]" } else { "" }
+            );
+            if let Err(e) = write!(
+                stdout,
+                "\x1b[H\x1b[J{}\n{}\n",
+                frames[index].render(self.color),
+                status
+            )
+            .and_then(|_| stdout.flush())
+            {
+                break Err(e);
+            }
+
+            let control = if !input_open {
+                thread::sleep(delay);
+                Err(RecvTimeoutError::Timeout)
+            } else if paused {
+                controls.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                controls.recv_timeout(delay)
+            };
+            match control {
+                Ok(Control::Toggle) => paused = !paused,
+                Ok(Control::Next) => {
+                    paused = true;
+                    index = (index + 1).min(last);
+                }
+                Ok(Control::Back) => {
+                    paused = true;
+                    index = index.saturating_sub(1);
+                }
+                Ok(Control::Faster) => delay = (delay / 2).max(MIN_DELAY),
+                Ok(Control::Slower) => delay = (delay * 2).min(MAX_DELAY),
+                Ok(Control::Quit) => break Ok(()),
+                Err(RecvTimeoutError::Disconnected) => {
+                    input_open = false;
+                    paused = false;
+                }
+                Err(RecvTimeoutError::Timeout) if index == last => break Ok(()),
+                Err(RecvTimeoutError::Timeout) => index += 1,
+            }
+        };
+        write!(stdout, "\x1b[?25h")?;
+        stdout.flush()?;
+
+        result
+    }
+}
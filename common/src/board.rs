@@ -0,0 +1,394 @@
+//! Plain-text board files shared by every chapter.
+//!
+//! ```text
+//! # lines starting with '#' are comments
+//! 5 5 10      # height width end_turn
+//! 2           # number of characters
+//! 2 1         # x y of each character
+//! 2 3
+//! 1 2 3 4 5   # `height` rows of `width` points
+//! ...
+//! ```
+//!
+//! `x` indexes the rows and `y` the columns, i.e. `points[x][y]`. A point
+//! written as `X` is a wall, which holds no points and cannot be entered.
+//! Points range from 0 to [`MAX_POINT`].
+//!
+//! A game record is a board followed by a `---` line and one line of
+//! whitespace-separated actions per turn.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::GameConfig;
+
+/// Largest point a cell may hold, so that scores summed over a game and
+/// scaled by the evaluations stay well within `i32`.
+pub const MAX_POINT: usize = 9999;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Board {
+    pub h: usize,
+    pub w: usize,
+    pub end_turn: usize,
+    pub characters: Vec<(i32, i32)>,
+    pub points: Vec<Vec<usize>>,
+    /// Wall cells in row-major order.
+    pub walls: Vec<(i32, i32)>,
+}
+
+#[derive(Debug)]
+pub enum BoardError {
+    Io(std::io::Error),
+    UnexpectedEof {
+        expected: &'static str,
+    },
+    InvalidNumber {
+        line: usize,
+        token: String,
+    },
+    WrongFieldCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    OutOfBounds {
+        line: usize,
+        x: i32,
+        y: i32,
+    },
+    OnWall {
+        x: i32,
+        y: i32,
+    },
+    SharedStart {
+        x: i32,
+        y: i32,
+    },
+    TrailingInput {
+        line: usize,
+    },
+    PointTooLarge {
+        line: usize,
+        point: usize,
+    },
+    Mismatch {
+        what: &'static str,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for BoardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoardError::Io(e) => write!(f, "cannot read board: {}", e),
+            BoardError::UnexpectedEof { expected } => {
+                write!(f, "unexpected end of board, expected {}", expected)
+            }
+            BoardError::InvalidNumber { line, token } => {
+                write!(f, "line {}: invalid number `{}`", line, token)
+            }
+            BoardError::WrongFieldCount {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected {} fields, found {}",
+                line, expected, found
+            ),
+            BoardError::OutOfBounds { line, x, y } => {
+                write!(f, "line {}: ({}, {}) is outside the board", line, x, y)
+            }
+            BoardError::OnWall { x, y } => write!(f, "character at ({}, {}) is on a wall", x, y),
+            BoardError::SharedStart { x, y } => {
+                write!(f, "more than one character starts at ({}, {})", x, y)
+            }
+            BoardError::TrailingInput { line } => {
+                write!(f, "line {}: unexpected input after the point grid", line)
+            }
+            BoardError::PointTooLarge { line, point } => write!(
+                f,
+                "line {}: point {} is larger than {}",
+                line, point, MAX_POINT
+            ),
+            BoardError::Mismatch {
+                what,
+                expected,
+                found,
+            } => write!(f, "{} must be {}, found {}", what, expected, found),
+        }
+    }
+}
+
+impl std::error::Error for BoardError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BoardError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for BoardError {
+    fn from(e: std::io::Error) -> Self {
+        BoardError::Io(e)
+    }
+}
+
+/// Yields `(line number, fields)` for every non-empty line, with comments stripped.
+fn content_lines(s: &str) -> impl Iterator<Item = (usize, Vec<&str>)> {
+    s.lines().enumerate().filter_map(|(i, line)| {
+        let line = line.split('#').next().unwrap_or("");
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            None
+        } else {
+            Some((i + 1, fields))
+        }
+    })
+}
+
+fn parse_fields<T: FromStr>(
+    line: usize,
+    fields: &[&str],
+    expected: usize,
+) -> Result<Vec<T>, BoardError> {
+    if fields.len() != expected {
+        return Err(BoardError::WrongFieldCount {
+            line,
+            expected,
+            found: fields.len(),
+        });
+    }
+    fields
+        .iter()
+        .map(|token| {
+            token.parse().map_err(|_| BoardError::InvalidNumber {
+                line,
+                token: token.to_string(),
+            })
+        })
+        .collect()
+}
+
+impl Board {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Board, BoardError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    pub fn config(&self) -> GameConfig {
+        GameConfig::new(self.h, self.w, self.end_turn, self.characters.len())
+    }
+
+    /// Fails unless the board has exactly `expected` characters.
+    pub fn expect_characters(&self, expected: usize) -> Result<(), BoardError> {
+        if self.characters.len() != expected {
+            return Err(BoardError::Mismatch {
+                what: "number of characters",
+                expected,
+                found: self.characters.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Fails if the board has walls, for games that have none.
+    pub fn expect_no_walls(&self) -> Result<(), BoardError> {
+        if !self.walls.is_empty() {
+            return Err(BoardError::Mismatch {
+                what: "number of walls",
+                expected: 0,
+                found: self.walls.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Fails if two characters start on the same cell, for games where
+    /// characters never share one.
+    pub fn expect_distinct_characters(&self) -> Result<(), BoardError> {
+        for (i, &(x, y)) in self.characters.iter().enumerate() {
+            if self.characters[..i].contains(&(x, y)) {
+                return Err(BoardError::SharedStart { x, y });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_wall(&self, x: i32, y: i32) -> bool {
+        self.walls.contains(&(x, y))
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {} {}", self.h, self.w, self.end_turn)?;
+        writeln!(f, "{}", self.characters.len())?;
+        for (x, y) in &self.characters {
+            writeln!(f, "{} {}", x, y)?;
+        }
+        for (x, row) in self.points.iter().enumerate() {
+            let row: Vec<String> = row
+                .iter()
+                .enumerate()
+                .map(|(y, point)| {
+                    if self.is_wall(x as i32, y as i32) {
+                        String::from("X")
+                    } else {
+                        point.to_string()
+                    }
+                })
+                .collect();
+            writeln!(f, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Board {
+    type Err = BoardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = content_lines(s);
+
+        let (line, fields) = lines.next().ok_or(BoardError::UnexpectedEof {
+            expected: "height, width and end turn",
+        })?;
+        let header: Vec<usize> = parse_fields(line, &fields, 3)?;
+        let (h, w, end_turn) = (header[0], header[1], header[2]);
+
+        let (line, fields) = lines.next().ok_or(BoardError::UnexpectedEof {
+            expected: "number of characters",
+        })?;
+        let character_n = parse_fields::<usize>(line, &fields, 1)?[0];
+
+        let mut board = Board {
+            h,
+            w,
+            end_turn,
+            // The counts come from the file, so the vectors grow as lines
+            // are read instead of being allocated up front.
+            characters: Vec::new(),
+            points: Vec::new(),
+            walls: Vec::new(),
+        };
+        for _ in 0..character_n {
+            let (line, fields) = lines.next().ok_or(BoardError::UnexpectedEof {
+                expected: "character position",
+            })?;
+            let position: Vec<i32> = parse_fields(line, &fields, 2)?;
+            let (x, y) = (position[0], position[1]);
+            if !board.config().is_inside(x, y) {
+                return Err(BoardError::OutOfBounds { line, x, y });
+            }
+            board.characters.push((x, y));
+        }
+
+        for x in 0..h {
+            let (line, mut fields) = lines.next().ok_or(BoardError::UnexpectedEof {
+                expected: "row of points",
+            })?;
+            for (y, field) in fields.iter_mut().enumerate() {
+                if *field == "X" {
+                    board.walls.push((x as i32, y as i32));
+                    *field = "0";
+                }
+            }
+            let row: Vec<usize> = parse_fields(line, &fields, w)?;
+            if let Some(&point) = row.iter().find(|&&point| point > MAX_POINT) {
+                return Err(BoardError::PointTooLarge { line, point });
+            }
+            board.points.push(row);
+        }
+        if let Some(&(x, y)) = board.characters.iter().find(|&&(x, y)| board.is_wall(x, y)) {
+            return Err(BoardError::OnWall { x, y });
+        }
+
+        if let Some((line, _)) = lines.next() {
+            return Err(BoardError::TrailingInput { line });
+        }
+
+        Ok(board)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRecord {
+    pub board: Board,
+    pub actions: Vec<Vec<usize>>,
+}
+
+impl GameRecord {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<GameRecord, BoardError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), BoardError> {
+        Ok(fs::write(path, self.to_string())?)
+    }
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.board)?;
+        writeln!(f, "---")?;
+        for actions in &self.actions {
+            let actions: Vec<String> = actions.iter().map(|act| act.to_string()).collect();
+            writeln!(f, "{}", actions.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = BoardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines: Vec<&str> = s.lines().collect();
+        let separator = lines.iter().position(|line| line.trim() == "---").ok_or(
+            BoardError::UnexpectedEof {
+                expected: "`---` before the actions",
+            },
+        )?;
+        let board = lines[..separator].join("\n").parse()?;
+
+        let mut actions = Vec::new();
+        for (line, fields) in content_lines(&lines[separator + 1..].join("\n")) {
+            let line = line + separator + 1;
+            actions.push(parse_fields(line, &fields, fields.len())?);
+        }
+
+        Ok(GameRecord { board, actions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_counts_fail_without_allocating() {
+        let error = "5 5 5\n999999999999999999\n0 0\n"
+            .parse::<Board>()
+            .unwrap_err();
+
+        assert!(matches!(error, BoardError::UnexpectedEof { .. }));
+    }
+
+    #[test]
+    fn points_above_the_maximum_are_rejected() {
+        let error = "1 2 2\n1\n0 0\n1 10000\n".parse::<Board>().unwrap_err();
+
+        assert!(matches!(
+            error,
+            BoardError::PointTooLarge {
+                line: 4,
+                point: 10000
+            }
+        ));
+    }
+}
@@ -0,0 +1,257 @@
+//! Minimal command-line parsing shared by the chapter binaries.
+//!
+//! Arguments are a subcommand followed by `--key value` options. An option
+//! without a value (followed by another option or nothing) is a flag.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::GameConfig;
+
+#[derive(Debug)]
+pub enum CliError {
+    UnknownCommand(String),
+    UnexpectedArgument(String),
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue { key: String, value: String },
+    Invalid(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UnknownCommand(command) => write!(f, "unknown command `{}`", command),
+            CliError::UnexpectedArgument(arg) => write!(f, "unexpected argument `{}`", arg),
+            CliError::UnknownOption(key) => write!(f, "unknown option `--{}`", key),
+            CliError::MissingValue(key) => write!(f, "option `--{}` needs a value", key),
+            CliError::InvalidValue { key, value } => {
+                write!(f, "invalid value `{}` for `--{}`", value, key)
+            }
+            CliError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Quotes `field` for a CSV row if it holds a comma, a quote or a line
+/// break, doubling its quotes as RFC 4180 does. AI specs such as
+/// `beam:width=20,ms=1000` need it.
+pub fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Parses `a..b` as a half-open range of seeds and `n` as `0..n`. Empty
+/// ranges are rejected.
+pub fn parse_seeds(s: &str) -> Option<Range<u64>> {
+    let range = match s.split_once("..") {
+        Some((start, end)) => start.parse().ok()?..end.parse().ok()?,
+        None => 0..s.parse().ok()?,
+    };
+
+    (!range.is_empty()).then_some(range)
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub command: Option<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args, CliError> {
+        let mut args = args.into_iter().peekable();
+        let command = match args.peek() {
+            Some(arg) if !arg.starts_with("--") => args.next(),
+            _ => None,
+        };
+
+        let mut options = Vec::new();
+        while let Some(arg) = args.next() {
+            let key = arg
+                .strip_prefix("--")
+                .ok_or_else(|| CliError::UnexpectedArgument(arg.clone()))?;
+            if let Some((key, value)) = key.split_once('=') {
+                options.push((key.to_string(), Some(value.to_string())));
+                continue;
+            }
+            let value = match args.peek() {
+                Some(next) if !next.starts_with("--") => args.next(),
+                _ => None,
+            };
+            options.push((key.to_string(), value));
+        }
+
+        Ok(Args { command, options })
+    }
+
+    /// Removes every occurrence of `key` and returns their values in order.
+    pub fn take_all(&mut self, key: &str) -> Result<Vec<String>, CliError> {
+        let mut values = Vec::new();
+        let mut rest = Vec::new();
+        for (k, v) in self.options.drain(..) {
+            if k == key {
+                values.push(v.ok_or(CliError::MissingValue(k))?);
+            } else {
+                rest.push((k, v));
+            }
+        }
+        self.options = rest;
+
+        Ok(values)
+    }
+
+    /// Removes `key` and parses its last value.
+    pub fn take<T: FromStr>(&mut self, key: &str) -> Result<Option<T>, CliError> {
+        match self.take_all(key)?.pop() {
+            Some(value) => value.parse().map(Some).map_err(|_| CliError::InvalidValue {
+                key: key.to_string(),
+                value,
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes `key` and parses its last value as a comma separated list.
+    pub fn take_list<T: FromStr>(&mut self, key: &str) -> Result<Option<Vec<T>>, CliError> {
+        match self.take_all(key)?.pop() {
+            Some(value) => value
+                .split(',')
+                .map(|item| {
+                    item.trim().parse().map_err(|_| CliError::InvalidValue {
+                        key: key.to_string(),
+                        value: value.clone(),
+                    })
+                })
+                .collect::<Result<_, _>>()
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn take_or<T: FromStr>(&mut self, key: &str, default: T) -> Result<T, CliError> {
+        Ok(self.take(key)?.unwrap_or(default))
+    }
+
+    /// Like [`Args::take_or`], failing if the value is below `min`.
+    pub fn take_at_least<T>(&mut self, key: &str, default: T, min: T) -> Result<T, CliError>
+    where
+        T: FromStr + PartialOrd + fmt::Display,
+    {
+        let value = self.take_or(key, default)?;
+        if value < min {
+            return Err(CliError::Invalid(format!(
+                "`--{}` must be at least {}, got {}",
+                key, min, value
+            )));
+        }
+
+        Ok(value)
+    }
+
+    /// Removes the flag `key` and reports whether it was given.
+    pub fn take_flag(&mut self, key: &str) -> Result<bool, CliError> {
+        let position = self.options.iter().position(|(k, _)| k == key);
+        match position.map(|i| self.options.remove(i)) {
+            Some((key, Some(value))) => Err(CliError::InvalidValue { key, value }),
+            Some((_, None)) => Ok(true),
+            None => Ok(false),
+        }
+    }
+
+    /// Reads `--seeds a..b` or, failing that, `--games n` as `0..n`.
+    pub fn take_seeds(&mut self, default_games: u64) -> Result<Range<u64>, CliError> {
+        let games = self.take_at_least("games", default_games, 1)?;
+        match self.take_all("seeds")?.pop() {
+            Some(value) => parse_seeds(&value).ok_or(CliError::InvalidValue {
+                key: String::from("seeds"),
+                value,
+            }),
+            None => Ok(0..games),
+        }
+    }
+
+    /// Overrides fields of `default` with `--height`, `--width`, `--end-turn` and `--characters`,
+    /// none of which may be zero.
+    pub fn take_config(&mut self, default: GameConfig) -> Result<GameConfig, CliError> {
+        Ok(GameConfig::new(
+            self.take_at_least("height", default.h, 1)?,
+            self.take_at_least("width", default.w, 1)?,
+            self.take_at_least("end-turn", default.end_turn, 1)?,
+            self.take_at_least("characters", default.character_n, 1)?,
+        ))
+    }
+
+    /// Fails if any option was not consumed by the command.
+    pub fn finish(self) -> Result<(), CliError> {
+        match self.options.into_iter().next() {
+            Some((key, _)) => Err(CliError::UnknownOption(key)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Names of the four moves, indexed like the `dx`/`dy` tables of every maze.
+pub const DIRECTION_NAMES: [&str; 4] = ["DOWN", "UP", "RIGHT", "LEFT"];
+
+/// Accepts direction names, `hjkl` and `wasd`, ignoring case.
+pub fn parse_direction(input: &str) -> Option<usize> {
+    match input.trim().to_ascii_lowercase().as_str() {
+        "down" | "j" | "s" => Some(0),
+        "up" | "k" | "w" => Some(1),
+        "right" | "l" | "d" => Some(2),
+        "left" | "h" | "a" => Some(3),
+        _ => None,
+    }
+}
+
+/// Prompts until a legal direction is read from stdin. Returns `None` on end of input or `q`.
+pub fn read_direction(prompt: &str, legal_actions: &[usize]) -> Option<usize> {
+    let legal_names: Vec<&str> = legal_actions
+        .iter()
+        .map(|&act| DIRECTION_NAMES[act])
+        .collect();
+    let stdin = io::stdin();
+    loop {
+        print!("{} [{}]: ", prompt, legal_names.join("/"));
+        io::stdout().flush().ok()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).ok()? == 0 || line.trim() == "q" {
+            return None;
+        }
+        match parse_direction(&line) {
+            Some(act) if legal_actions.contains(&act) => return Some(act),
+            Some(act) => println!("{} is not a legal move", DIRECTION_NAMES[act]),
+            None => println!(
+                "unknown move `{}`, use a direction, hjkl or wasd",
+                line.trim()
+            ),
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Flat point grids shared by the maze states.
+//!
+//! A [`PointGrid`] keeps the initial points of a board behind an [`Arc`] and
+//! marks the consumed cells in a [`CellSet`], so cloning a state copies one
+//! word per 64 cells instead of every row of points.
+
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
+
+/// Row-major `h` x `w` grid indexed by `[(x, y)]`, like `points[x][y]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid<T> {
+    h: usize,
+    w: usize,
+    cells: Vec<T>,
+}
+
+impl<T> Grid<T> {
+    pub const fn h(&self) -> usize {
+        self.h
+    }
+
+    pub const fn w(&self) -> usize {
+        self.w
+    }
+
+    /// Position of `(x, y)` in the flat storage, also used as a [`CellSet`] index.
+    /// A `y` past the last column would land in the next row, so debug builds
+    /// check both coordinates.
+    pub const fn cell(&self, x: usize, y: usize) -> usize {
+        debug_assert!(x < self.h && y < self.w, "cell outside the grid");
+        x * self.w + y
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        self.cells.chunks(self.w)
+    }
+}
+
+impl<T: Clone> Grid<T> {
+    pub fn new(h: usize, w: usize, value: T) -> Grid<T> {
+        Grid {
+            h,
+            w,
+            cells: vec![value; h * w],
+        }
+    }
+
+    /// Panics unless every row has the length of the first one.
+    pub fn from_rows(rows: &[Vec<T>]) -> Grid<T> {
+        let w = rows.first().map_or(0, Vec::len);
+        assert!(rows.iter().all(|row| row.len() == w), "ragged grid");
+
+        Grid {
+            h: rows.len(),
+            w,
+            cells: rows.concat(),
+        }
+    }
+
+    pub fn to_rows(&self) -> Vec<Vec<T>> {
+        self.rows().map(<[T]>::to_vec).collect()
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        &self.cells[self.cell(x, y)]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+        let cell = self.cell(x, y);
+        &mut self.cells[cell]
+    }
+}
+
+/// One bit per cell of a grid.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CellSet {
+    bits: Vec<u64>,
+}
+
+impl CellSet {
+    pub fn new(cells: usize) -> CellSet {
+        CellSet {
+            bits: vec![0; cells.div_ceil(64)],
+        }
+    }
+
+    pub fn contains(&self, cell: usize) -> bool {
+        self.bits[cell / 64] >> (cell % 64) & 1 == 1
+    }
+
+    /// Returns whether `cell` was not in the set yet.
+    pub fn insert(&mut self, cell: usize) -> bool {
+        let newly_inserted = !self.contains(cell);
+        self.bits[cell / 64] |= 1 << (cell % 64);
+        newly_inserted
+    }
+
+    /// Returns whether `cell` was in the set.
+    pub fn remove(&mut self, cell: usize) -> bool {
+        let was_present = self.contains(cell);
+        self.bits[cell / 64] &= !(1 << (cell % 64));
+        was_present
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|bits| bits.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&bits| bits == 0)
+    }
+}
+
+/// Points of a board with the consumed cells masked out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PointGrid {
+    initial: Arc<Grid<usize>>,
+    consumed: CellSet,
+}
+
+impl PointGrid {
+    pub fn new(points: Grid<usize>) -> PointGrid {
+        PointGrid {
+            consumed: CellSet::new(points.h() * points.w()),
+            initial: Arc::new(points),
+        }
+    }
+
+    pub fn from_rows(rows: &[Vec<usize>]) -> PointGrid {
+        PointGrid::new(Grid::from_rows(rows))
+    }
+
+    pub fn h(&self) -> usize {
+        self.initial.h()
+    }
+
+    pub fn w(&self) -> usize {
+        self.initial.w()
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> usize {
+        self.initial.cell(x, y)
+    }
+
+    /// Points left on `(x, y)`, zero once consumed.
+    pub fn get(&self, x: usize, y: usize) -> usize {
+        if self.consumed.contains(self.cell(x, y)) {
+            0
+        } else {
+            self.initial[(x, y)]
+        }
+    }
+
+    /// Takes the points left on `(x, y)` and returns them. Cells without
+    /// points are not marked, so the consumed set only holds real pickups.
+    pub fn consume(&mut self, x: usize, y: usize) -> usize {
+        let point = self.get(x, y);
+        if point > 0 {
+            self.consumed.insert(self.cell(x, y));
+        }
+        point
+    }
+
+    /// Puts the points of a consumed cell back.
+    pub fn restore(&mut self, x: usize, y: usize) {
+        let cell = self.cell(x, y);
+        self.consumed.remove(cell);
+    }
+
+    /// Places `value` points on `(x, y)`, consumed or not. The shared
+    /// initial grid is copied first if other states still hold it.
+    pub fn set(&mut self, x: usize, y: usize, value: usize) {
+        let cell = self.cell(x, y);
+        self.consumed.remove(cell);
+        Arc::make_mut(&mut self.initial)[(x, y)] = value;
+    }
+
+    /// The points of every cell, consumed cells included.
+    pub fn initial(&self) -> &Grid<usize> {
+        &self.initial
+    }
+
+    pub fn consumed(&self) -> &CellSet {
+        &self.consumed
+    }
+
+    pub fn to_rows(&self) -> Vec<Vec<usize>> {
+        (0..self.h())
+            .map(|x| (0..self.w()).map(|y| self.get(x, y)).collect())
+            .collect()
+    }
+}
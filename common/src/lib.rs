@@ -1,3 +1,10 @@
+pub mod animate;
+pub mod board;
+pub mod cli;
+pub mod grid;
+pub mod spec;
+pub mod tune;
+
 use std::sync::Mutex;
 use std::time::Instant;
 
@@ -51,4 +58,84 @@ impl TimeKeeper {
     pub fn is_time_over(&self) -> bool {
         (Instant::now() - self.start_time).as_millis() >= self.threshold
     }
+
+    /// Fraction of the threshold elapsed, 1 or more once the time is over.
+    pub fn progress(&self) -> f64 {
+        (Instant::now() - self.start_time).as_secs_f64() * 1000.0 / self.threshold as f64
+    }
+}
+
+/// Board size, turn limit and character count of a game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameConfig {
+    pub h: usize,
+    pub w: usize,
+    pub end_turn: usize,
+    pub character_n: usize,
+}
+
+impl GameConfig {
+    pub const fn new(h: usize, w: usize, end_turn: usize, character_n: usize) -> GameConfig {
+        GameConfig {
+            h,
+            w,
+            end_turn,
+            character_n,
+        }
+    }
+
+    pub const fn is_inside(&self, x: i32, y: i32) -> bool {
+        x >= 0 && (x as usize) < self.h && y >= 0 && (y as usize) < self.w
+    }
+}
+
+/// Clones and advances per second, as reported by [`measure_throughput`].
+#[derive(Debug, Clone, Copy)]
+pub struct Throughput {
+    pub clones: f64,
+    pub advances: f64,
+}
+
+impl Throughput {
+    pub fn print(&self, format: cli::OutputFormat) {
+        match format {
+            cli::OutputFormat::Text => {
+                println!("clone: {:.0} states/s", self.clones);
+                println!("advance: {:.0} states/s", self.advances);
+            }
+            cli::OutputFormat::Csv => {
+                println!("operation,per_second");
+                println!("clone,{:.0}", self.clones);
+                println!("advance,{:.0}", self.advances);
+            }
+        }
+    }
+}
+
+/// Clones every state and advances every clone `rounds` times, timing both
+/// steps separately. `advance(i, state)` advances a clone of `states[i]`.
+pub fn measure_throughput<S: Clone>(
+    states: &[S],
+    rounds: usize,
+    advance: impl Fn(usize, &mut S),
+) -> Throughput {
+    let mut clone_time = 0.0;
+    let mut advance_time = 0.0;
+    for _ in 0..rounds {
+        let start_time = Instant::now();
+        let mut clones = states.to_vec();
+        clone_time += start_time.elapsed().as_secs_f64();
+
+        let start_time = Instant::now();
+        for (i, state) in clones.iter_mut().enumerate() {
+            advance(i, state);
+        }
+        advance_time += start_time.elapsed().as_secs_f64();
+    }
+    let operations = (states.len() * rounds) as f64;
+
+    Throughput {
+        clones: operations / clone_time,
+        advances: operations / advance_time,
+    }
 }
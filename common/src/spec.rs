@@ -0,0 +1,247 @@
+//! Textual AI specifications such as `mcts:playouts=1000,c=1.4`.
+//!
+//! A spec is an algorithm name, optionally followed by `:` and a comma
+//! separated list of `key=value` parameters. A [`Registry`] resolves specs
+//! against the algorithms a chapter offers.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecError {
+    Malformed(String),
+    UnknownAlgorithm {
+        name: String,
+        available: Vec<&'static str>,
+    },
+    UnknownParam {
+        algorithm: &'static str,
+        key: String,
+        available: Vec<&'static str>,
+    },
+    DuplicateParam(String),
+    InvalidValue {
+        key: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecError::Malformed(part) => {
+                write!(f, "malformed parameter `{}`, expected key=value", part)
+            }
+            SpecError::UnknownAlgorithm { name, available } => write!(
+                f,
+                "unknown algorithm `{}` (available: {})",
+                name,
+                available.join(", ")
+            ),
+            SpecError::UnknownParam {
+                algorithm,
+                key,
+                available,
+            } => {
+                if available.is_empty() {
+                    write!(f, "`{}` takes no parameters, found `{}`", algorithm, key)
+                } else {
+                    write!(
+                        f,
+                        "unknown parameter `{}` for `{}` (available: {})",
+                        key,
+                        algorithm,
+                        available.join(", ")
+                    )
+                }
+            }
+            SpecError::DuplicateParam(key) => write!(f, "parameter `{}` is given twice", key),
+            SpecError::InvalidValue { key, value } => {
+                write!(f, "invalid value `{}` for parameter `{}`", value, key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AiSpec {
+    pub name: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl FromStr for AiSpec {
+    type Err = SpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, rest) = s.split_once(':').unwrap_or((s, ""));
+        let mut params: Vec<(String, String)> = Vec::new();
+        for part in rest
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+        {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| SpecError::Malformed(part.to_string()))?;
+            let (key, value) = (key.trim(), value.trim());
+            if key.is_empty() || value.is_empty() {
+                return Err(SpecError::Malformed(part.to_string()));
+            }
+            if params.iter().any(|(k, _)| k == key) {
+                return Err(SpecError::DuplicateParam(key.to_string()));
+            }
+            params.push((key.to_string(), value.to_string()));
+        }
+
+        Ok(AiSpec {
+            name: name.trim().to_string(),
+            params,
+        })
+    }
+}
+
+impl fmt::Display for AiSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for (i, (key, value)) in self.params.iter().enumerate() {
+            let separator = if i == 0 { ':' } else { ',' };
+            write!(f, "{}{}={}", separator, key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// A parameter an algorithm accepts. Without a default the algorithm derives
+/// the value from the state it searches.
+pub struct ParamDef {
+    pub name: &'static str,
+    pub default: Option<&'static str>,
+    pub help: &'static str,
+}
+
+/// Parameters of a spec, with defaults filled in.
+pub struct Params {
+    values: Vec<(&'static str, Option<String>)>,
+}
+
+impl Params {
+    /// Returns `None` for a parameter that has no default and was not given.
+    pub fn get_opt<T: FromStr>(&self, key: &str) -> Result<Option<T>, SpecError> {
+        let value = self
+            .values
+            .iter()
+            .find(|(k, _)| *k == key)
+            .and_then(|(_, v)| v.as_ref());
+        match value {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| SpecError::InvalidValue {
+                    key: key.to_string(),
+                    value: value.clone(),
+                }),
+            None => Ok(None),
+        }
+    }
+
+    /// Panics if `key` is neither given nor has a default, which is a bug in the registry.
+    pub fn get<T: FromStr>(&self, key: &str) -> Result<T, SpecError> {
+        Ok(self
+            .get_opt(key)?
+            .unwrap_or_else(|| panic!("parameter `{}` has no default", key)))
+    }
+
+    /// Like [`Params::get_opt`], failing if the value is below `min`.
+    pub fn get_opt_at_least<T>(&self, key: &str, min: T) -> Result<Option<T>, SpecError>
+    where
+        T: FromStr + PartialOrd + fmt::Display,
+    {
+        match self.get_opt::<T>(key)? {
+            Some(value) if value < min => Err(SpecError::InvalidValue {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            value => Ok(value),
+        }
+    }
+
+    /// Like [`Params::get`], failing if the value is below `min`.
+    pub fn get_at_least<T>(&self, key: &str, min: T) -> Result<T, SpecError>
+    where
+        T: FromStr + PartialOrd + fmt::Display,
+    {
+        Ok(self
+            .get_opt_at_least(key, min)?
+            .unwrap_or_else(|| panic!("parameter `{}` has no default", key)))
+    }
+}
+
+pub struct Algorithm<T> {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub params: &'static [ParamDef],
+    pub build: fn(&Params) -> Result<T, SpecError>,
+}
+
+pub struct Registry<T> {
+    algorithms: Vec<Algorithm<T>>,
+}
+
+impl<T> Registry<T> {
+    pub fn new(algorithms: Vec<Algorithm<T>>) -> Registry<T> {
+        Registry { algorithms }
+    }
+
+    pub fn build(&self, spec: &AiSpec) -> Result<T, SpecError> {
+        let algorithm = self
+            .algorithms
+            .iter()
+            .find(|algorithm| algorithm.name == spec.name)
+            .ok_or_else(|| SpecError::UnknownAlgorithm {
+                name: spec.name.clone(),
+                available: self.algorithms.iter().map(|a| a.name).collect(),
+            })?;
+
+        for (key, _) in &spec.params {
+            if !algorithm.params.iter().any(|param| param.name == key) {
+                return Err(SpecError::UnknownParam {
+                    algorithm: algorithm.name,
+                    key: key.clone(),
+                    available: algorithm.params.iter().map(|param| param.name).collect(),
+                });
+            }
+        }
+        let values = algorithm
+            .params
+            .iter()
+            .map(|param| {
+                let given = spec.params.iter().find(|(key, _)| key == param.name);
+                let value = match given {
+                    Some((_, value)) => Some(value.clone()),
+                    None => param.default.map(String::from),
+                };
+                (param.name, value)
+            })
+            .collect();
+
+        (algorithm.build)(&Params { values })
+    }
+
+    /// Lists every algorithm with its parameters and defaults.
+    pub fn describe(&self) -> String {
+        let mut s = String::new();
+        for algorithm in &self.algorithms {
+            s += &format!("{:<24}{}\n", algorithm.name, algorithm.help);
+            for param in algorithm.params {
+                let param_str = match param.default {
+                    Some(default) => format!("{}={}", param.name, default),
+                    None => param.name.to_string(),
+                };
+                s += &format!("    {:<19} {}\n", param_str, param.help);
+            }
+        }
+        s
+    }
+}
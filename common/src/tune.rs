@@ -0,0 +1,219 @@
+//! Offline tuning of AI parameters.
+//!
+//! Candidates are drawn at random from the ranges given with `--param`, and
+//! the best of them is refined by SPSA (simultaneous perturbation stochastic
+//! approximation), which estimates the gradient from two evaluations per step
+//! whatever the number of parameters. Every candidate is scored by a
+//! chapter's own match harness, passed in as the objective.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::cli::{Args, CliError};
+use crate::spec::AiSpec;
+
+/// Range a parameter is searched over, written `name=low..high`. A range
+/// whose bounds both parse as integers gives integer values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamRange {
+    pub name: String,
+    pub low: f64,
+    pub high: f64,
+    pub integer: bool,
+}
+
+impl ParamRange {
+    /// The value at `t` in `[0, 1]` along the range.
+    fn value(&self, t: f64) -> String {
+        let value = self.low + (self.high - self.low) * t.clamp(0.0, 1.0);
+        if self.integer {
+            format!("{}", value.round() as i64)
+        } else {
+            format!("{:.3}", value)
+        }
+    }
+}
+
+impl FromStr for ParamRange {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, range) = s.split_once('=').ok_or(())?;
+        let (low, high) = range.split_once("..").ok_or(())?;
+        let (low, high) = (low.trim(), high.trim());
+        let integer = low.parse::<i64>().is_ok() && high.parse::<i64>().is_ok();
+        let (low, high) = (
+            low.parse::<f64>().map_err(|_| ())?,
+            high.parse::<f64>().map_err(|_| ())?,
+        );
+        if name.trim().is_empty() || low.is_nan() || high.is_nan() || low > high {
+            return Err(());
+        }
+
+        Ok(ParamRange {
+            name: name.trim().to_string(),
+            low,
+            high,
+            integer,
+        })
+    }
+}
+
+/// How long to search and where to write the result, read from the
+/// command line by [`TuneOptions::take`].
+#[derive(Debug, Clone)]
+pub struct TuneOptions {
+    pub ranges: Vec<ParamRange>,
+    /// Random candidates evaluated before SPSA starts from the best one.
+    pub samples: usize,
+    /// SPSA steps, each evaluating two candidates.
+    pub iterations: usize,
+    /// Seed of the candidate generator, independent of the game seeds.
+    pub seed: u64,
+    pub out: Option<String>,
+}
+
+impl TuneOptions {
+    /// Reads `--param` (repeatable, at least one), `--samples`,
+    /// `--iterations`, `--tune-seed` and `--out`.
+    pub fn take(args: &mut Args) -> Result<TuneOptions, CliError> {
+        let ranges = args
+            .take_all("param")?
+            .into_iter()
+            .map(|value| {
+                value.parse().map_err(|_| CliError::InvalidValue {
+                    key: String::from("param"),
+                    value,
+                })
+            })
+            .collect::<Result<Vec<ParamRange>, _>>()?;
+        if ranges.is_empty() {
+            return Err(CliError::MissingValue(String::from("param")));
+        }
+        let samples = args.take_at_least("samples", 20, 1)?;
+
+        Ok(TuneOptions {
+            ranges,
+            samples,
+            iterations: args.take_or("iterations", 20)?,
+            seed: args.take_or("tune-seed", 0)?,
+            out: args.take("out")?,
+        })
+    }
+}
+
+/// Best spec found and its score.
+#[derive(Debug, Clone)]
+pub struct Tuned {
+    pub spec: AiSpec,
+    pub score: f64,
+    /// Distinct specs the objective was called on.
+    pub evaluations: usize,
+}
+
+impl Tuned {
+    /// Writes the spec on a line of its own, ready for `--ai`.
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, format!("{}\n", self.spec))
+    }
+}
+
+impl fmt::Display for Tuned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Best of {} candidates: {} ({:.4})",
+            self.evaluations, self.spec, self.score
+        )
+    }
+}
+
+/// Tunes the parameters of `base` named by `options.ranges`, keeping its
+/// other parameters, to maximise `objective`. A spec reached twice, as
+/// rounding integer parameters often does, is scored once.
+pub fn tune<E>(
+    base: &AiSpec,
+    options: &TuneOptions,
+    mut objective: impl FnMut(&AiSpec) -> Result<f64, E>,
+) -> Result<Tuned, E> {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let ranges = &options.ranges;
+    let mut scores: HashMap<String, f64> = HashMap::new();
+    let mut best: Option<Tuned> = None;
+    let mut evaluate = |point: &[f64]| -> Result<f64, E> {
+        let spec = spec_at(base, ranges, point);
+        let key = spec.to_string();
+        if let Some(&score) = scores.get(&key) {
+            return Ok(score);
+        }
+        let score = objective(&spec)?;
+        scores.insert(key, score);
+        if best.as_ref().is_none_or(|best| score > best.score) {
+            best = Some(Tuned {
+                spec,
+                score,
+                evaluations: 0,
+            });
+        }
+        Ok(score)
+    };
+
+    let mut point = Vec::new();
+    let mut point_score = f64::NEG_INFINITY;
+    for _ in 0..options.samples {
+        let sample: Vec<f64> = ranges.iter().map(|_| rng.gen::<f64>()).collect();
+        let score = evaluate(&sample)?;
+        if score > point_score {
+            point = sample;
+            point_score = score;
+        }
+    }
+
+    // Steps are taken on the unit cube, and the difference of the two
+    // scores is divided by their size so that the gains do not depend on
+    // the scale of the objective.
+    for k in 0..options.iterations {
+        let step = 0.2 / ((k + 1) as f64).powf(0.602);
+        let perturbation = 0.1 / ((k + 1) as f64).powf(0.101);
+        let delta: Vec<f64> = ranges
+            .iter()
+            .map(|_| if rng.gen::<bool>() { 1.0 } else { -1.0 })
+            .collect();
+        let shifted = |sign: f64| -> Vec<f64> {
+            point
+                .iter()
+                .zip(&delta)
+                .map(|(t, d)| (t + sign * perturbation * d).clamp(0.0, 1.0))
+                .collect()
+        };
+        let plus = evaluate(&shifted(1.0))?;
+        let minus = evaluate(&shifted(-1.0))?;
+        let scale = ((plus.abs() + minus.abs()) / 2.0).max(f64::EPSILON);
+        for (t, d) in point.iter_mut().zip(&delta) {
+            let gradient = (plus - minus) / (2.0 * perturbation * d) / scale;
+            *t = (*t + step * gradient).clamp(0.0, 1.0);
+        }
+    }
+    evaluate(&point)?;
+
+    let mut best = best.expect("at least one candidate is evaluated");
+    best.evaluations = scores.len();
+    Ok(best)
+}
+
+/// `base` with the parameters of `ranges` set to the values at `point`.
+fn spec_at(base: &AiSpec, ranges: &[ParamRange], point: &[f64]) -> AiSpec {
+    let mut spec = base.clone();
+    for (range, &t) in ranges.iter().zip(point) {
+        let value = range.value(t);
+        match spec.params.iter_mut().find(|(key, _)| *key == range.name) {
+            Some((_, old)) => *old = value,
+            None => spec.params.push((range.name.clone(), value)),
+        }
+    }
+
+    spec
+}